@@ -0,0 +1,327 @@
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
+use thiserror::Error;
+
+/// Errors that may be returned by the escrow program.
+///
+/// The discriminants are returned to clients as `ProgramError::Custom(code)`,
+/// so existing codes must never be renumbered; new variants go at the end.
+/// Clients can map a code back to its variant with
+/// `DecodeError::decode_custom_error_to_enum`.
+#[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum EscrowError {
+    /// The instruction data could not be decoded
+    #[error("Invalid instruction")]
+    InvalidInstruction = 0,
+
+    /// The signer is not the platform admin
+    #[error("Invalid admin")]
+    InvalidAdmin = 1,
+
+    /// The platform state account is not owned by this program
+    #[error("Platform state account not owned by the program")]
+    PlatformStateNotOwnedByProgram = 2,
+
+    /// The token state account is not owned by this program
+    #[error("Token state account not owned by the program")]
+    TokenStateNotOwnedByProgram = 3,
+
+    /// The listing account is not owned by this program
+    #[error("Listing account not owned by the program")]
+    ListingNotOwnedByProgram = 4,
+
+    /// The token state account does not match the derived PDA
+    #[error("Invalid token state PDA")]
+    InvalidTokenStatePda = 5,
+
+    /// The listing account does not match the derived PDA
+    #[error("Invalid listing PDA")]
+    InvalidListingPda = 6,
+
+    /// The seller did not sign the instruction
+    #[error("Seller signature missing")]
+    SellerNotSigner = 7,
+
+    /// The buyer did not sign the instruction
+    #[error("Taker signature missing")]
+    TakerNotSigner = 8,
+
+    /// The listing is already active
+    #[error("Listing already active")]
+    ListingAlreadyActive = 9,
+
+    /// The account passed as seller is not the seller of the listing
+    #[error("Not the seller of the listing")]
+    SellerMismatch = 10,
+
+    /// The escrow token account does not match the one stored in the listing
+    #[error("Escrow token account mismatch")]
+    EscrowTokenAccountMismatch = 11,
+
+    /// The treasury account does not match the platform state
+    #[error("Invalid treasury account")]
+    InvalidTreasuryAccount = 12,
+
+    /// The platform admin did not sign the instruction
+    #[error("Admin signature missing")]
+    AdminNotSigner = 13,
+
+    /// The signer is not the pending admin of the platform
+    #[error("Invalid pending admin")]
+    InvalidPendingAdmin = 14,
+
+    /// The platform fee exceeds the protocol maximum
+    #[error("Platform fee too high")]
+    FeeTooHigh = 15,
+
+    /// A price or fee calculation overflowed
+    #[error("Math overflow")]
+    MathOverflow = 16,
+
+    /// The mint account is not a valid SPL token mint
+    #[error("Invalid mint")]
+    InvalidMint = 17,
+
+    /// The mint does not match the one registered in the token state
+    #[error("Token mint mismatch")]
+    TokenMintMismatch = 18,
+
+    /// The quote mint is not allowed by the platform
+    #[error("Quote mint not allowed")]
+    QuoteMintNotAllowed = 19,
+
+    /// The quote mint does not match the one of the listing
+    #[error("Quote mint mismatch")]
+    QuoteMintMismatch = 20,
+
+    /// A quote token account has the wrong mint or owner
+    #[error("Invalid quote token account")]
+    InvalidQuoteTokenAccount = 21,
+
+    /// The listing holds fewer tokens than requested
+    #[error("Insufficient listed tokens")]
+    InsufficientListedTokens = 22,
+
+    /// The listing has expired
+    #[error("Listing expired")]
+    ListingExpired = 23,
+
+    /// The listing has not expired yet
+    #[error("Listing not expired")]
+    ListingNotExpired = 24,
+
+    /// The expiry timestamp is in the past
+    #[error("Invalid expiry")]
+    InvalidExpiry = 25,
+
+    /// The seller token account has the wrong mint or owner
+    #[error("Invalid seller token account")]
+    InvalidSellerTokenAccount = 26,
+
+    /// The token amount must be greater than zero
+    #[error("Invalid amount")]
+    InvalidAmount = 27,
+
+    /// The listing price is above the buyer's limit
+    #[error("Price above limit")]
+    PriceAboveLimit = 28,
+
+    /// The total to pay is above the buyer's limit
+    #[error("Total price above limit")]
+    TotalPriceAboveLimit = 29,
+
+    /// The escrow token account is not the listing PDA's associated token account
+    #[error("Invalid escrow token account")]
+    InvalidEscrowTokenAccount = 30,
+
+    /// An account that is written to was passed read-only
+    #[error("Account not writable")]
+    AccountNotWritable = 31,
+
+    /// The token program account is not the SPL token program
+    #[error("Invalid token program")]
+    InvalidTokenProgram = 32,
+
+    /// The system program account is not the system program
+    #[error("Invalid system program")]
+    InvalidSystemProgram = 33,
+
+    /// The associated token program account is not the associated token program
+    #[error("Invalid associated token program")]
+    InvalidAssociatedTokenProgram = 34,
+
+    /// The rent sysvar account is not the rent sysvar
+    #[error("Invalid rent sysvar")]
+    InvalidRentSysvar = 35,
+
+    /// The taker token account does not hold the listed mint
+    #[error("Invalid taker token account")]
+    InvalidTakerTokenAccount = 36,
+
+    /// The metadata account is not the Metaplex metadata account of the mint
+    #[error("Invalid metadata account")]
+    InvalidMetadataAccount = 37,
+
+    /// A royalty account does not belong to the matching verified creator
+    #[error("Creator account mismatch")]
+    CreatorMismatch = 38,
+
+    /// The mint is not an NFT (supply 1 and 0 decimals)
+    #[error("Mint is not an NFT")]
+    NotAnNft = 39,
+
+    /// NFT listings can only be bought whole
+    #[error("Partial fill not allowed")]
+    PartialFillNotAllowed = 40,
+
+    /// The collection of the NFT is not allowed on the platform
+    #[error("Collection not allowed")]
+    CollectionNotAllowed = 41,
+
+    /// The collection state account does not match the derived PDA
+    #[error("Invalid collection state PDA")]
+    InvalidCollectionStatePda = 42,
+
+    /// The offer is already active
+    #[error("Offer already active")]
+    OfferAlreadyActive = 43,
+
+    /// The offer account does not match the derived PDA
+    #[error("Invalid offer PDA")]
+    InvalidOfferPda = 44,
+
+    /// The offer account is not owned by this program
+    #[error("Offer account not owned by the program")]
+    OfferNotOwnedByProgram = 45,
+
+    /// The account passed as buyer is not the buyer of the offer
+    #[error("Not the buyer of the offer")]
+    BuyerMismatch = 46,
+
+    /// The buyer token account has the wrong mint or owner
+    #[error("Invalid buyer token account")]
+    InvalidBuyerTokenAccount = 47,
+
+    /// The offer asks for fewer tokens than sold
+    #[error("Insufficient offered tokens")]
+    InsufficientOfferedTokens = 48,
+
+    /// The order book account does not match the derived PDA
+    #[error("Invalid order book PDA")]
+    InvalidOrderBookPda = 49,
+
+    /// The order book account is not owned by this program
+    #[error("Order book account not owned by the program")]
+    OrderBookNotOwnedByProgram = 50,
+
+    /// The side of the order book has no room for another order
+    #[error("Order book full")]
+    OrderBookFull = 51,
+
+    /// No resting order with this id on this side of the book
+    #[error("Order not found")]
+    OrderNotFound = 52,
+
+    /// The signer does not own the order
+    #[error("Not the owner of the order")]
+    OrderOwnerMismatch = 53,
+
+    /// A counterparty account does not match the resting order it fills
+    #[error("Counterparty account mismatch")]
+    CounterpartyMismatch = 54,
+
+    /// The auction prices or timestamps are inconsistent
+    #[error("Invalid auction")]
+    InvalidAuction = 55,
+
+    /// Exchange, cancel and updates are not available on english auctions
+    #[error("Listing is sold by english auction")]
+    EnglishAuctionListing = 56,
+
+    /// The auction account is not the PDA of the listing
+    #[error("Invalid auction PDA")]
+    InvalidAuctionPda = 57,
+
+    /// The auction account is not owned by this program
+    #[error("Auction account not owned by program")]
+    AuctionNotOwnedByProgram = 58,
+
+    /// Bids are closed once the auction end time has passed
+    #[error("Auction ended")]
+    AuctionEnded = 59,
+
+    /// Auctions are settled after their end time only
+    #[error("Auction not ended")]
+    AuctionNotEnded = 60,
+
+    /// The bid is below the reserve price or the minimum increment
+    #[error("Bid too low")]
+    BidTooLow = 61,
+
+    /// The refunded account is not the previous highest bidder
+    #[error("Bidder mismatch")]
+    BidderMismatch = 62,
+
+    /// A swap has to ask for a mint other than the listed one
+    #[error("Invalid swap mint")]
+    InvalidSwapMint = 63,
+
+    /// The referral share is a part of the platform fee, at most all of it
+    #[error("Invalid referral share")]
+    InvalidReferralShare = 64,
+
+    /// The referrer state account is not the PDA of the referrer
+    #[error("Invalid referrer state PDA")]
+    InvalidReferrerStatePda = 65,
+
+    /// Fee recipients have to be distinct, include the treasury and have
+    /// weights summing to 10,000 basis points
+    #[error("Invalid fee split")]
+    InvalidFeeSplit = 66,
+
+    /// A fee recipient account does not match the fee split of the platform
+    #[error("Fee recipient mismatch")]
+    FeeRecipientMismatch = 67,
+
+    /// The order is worth less than the minimum order size of the book
+    #[error("Order too small")]
+    OrderTooSmall = 68,
+
+    /// The payer of a new account did not sign the transaction
+    #[error("Payer not signer")]
+    PayerNotSigner = 69,
+
+    /// The referrer of a trade is its taker or its seller
+    #[error("Invalid referrer")]
+    InvalidReferrer = 70,
+
+    /// The creator royalties of a sale exceed the seller's share of its price
+    #[error("Royalties exceed proceeds")]
+    RoyaltiesExceedProceeds = 71,
+}
+
+impl From<EscrowError> for ProgramError {
+    fn from(e: EscrowError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for EscrowError {
+    fn type_of() -> &'static str {
+        "EscrowError"
+    }
+}
+
+impl PrintProgramError for EscrowError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
@@ -0,0 +1,153 @@
+use solana_program::program_error::ProgramError;
+use std::convert::TryFrom;
+
+use crate::error::EscrowError;
+
+/// Denominator for fees expressed in basis points
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Hard protocol maximum for the platform fee (10%)
+pub const MAX_PLATFORM_FEE_BPS: u64 = 1_000;
+
+/// Total price of `token_amount` base units at `price_per_token` lamports per
+/// whole token of a mint with `decimals`, rounded up in favour of the seller
+pub fn total_price(
+    token_amount: u64,
+    price_per_token: u64,
+    decimals: u8,
+) -> Result<u64, ProgramError> {
+    let scale = 10u128
+        .checked_pow(decimals as u32)
+        .ok_or(EscrowError::MathOverflow)?;
+    let total = (token_amount as u128)
+        .checked_mul(price_per_token as u128)
+        .and_then(|value| value.checked_add(scale - 1))
+        .map(|value| value / scale)
+        .ok_or(EscrowError::MathOverflow)?;
+    u64::try_from(total).map_err(|_| EscrowError::MathOverflow.into())
+}
+
+/// Most base units of a mint with `decimals` that `budget` lamports buy at
+/// `price_per_token` lamports per whole token, `total_price` of it never
+/// exceeds `budget`
+pub fn affordable_amount(
+    budget: u64,
+    price_per_token: u64,
+    decimals: u8,
+) -> Result<u64, ProgramError> {
+    if price_per_token == 0 {
+        return Ok(u64::MAX);
+    }
+    let scale = 10u128
+        .checked_pow(decimals as u32)
+        .ok_or(EscrowError::MathOverflow)?;
+    let amount = (budget as u128)
+        .checked_mul(scale)
+        .map(|value| value / price_per_token as u128)
+        .ok_or(EscrowError::MathOverflow)?;
+    Ok(u64::try_from(amount).unwrap_or(u64::MAX))
+}
+
+/// Fee of `fee_bps` basis points on `amount`, rounded up so the platform never
+/// loses dust to the seller
+pub fn fee_amount(amount: u64, fee_bps: u64) -> Result<u64, ProgramError> {
+    let denominator = BPS_DENOMINATOR as u128;
+    let fee = (amount as u128)
+        .checked_mul(fee_bps as u128)
+        .and_then(|value| value.checked_add(denominator - 1))
+        .map(|value| value / denominator)
+        .ok_or(EscrowError::MathOverflow)?;
+    u64::try_from(fee).map_err(|_| EscrowError::MathOverflow.into())
+}
+
+/// Splits `total` into the platform fee and the remainder owed to the seller
+pub fn split_fee(total: u64, fee_bps: u64) -> Result<(u64, u64), ProgramError> {
+    let fee = fee_amount(total, fee_bps)?;
+    let remainder = total.checked_sub(fee).ok_or(EscrowError::MathOverflow)?;
+    Ok((fee, remainder))
+}
+
+/// Part of `royalty` owed to a creator holding `share` percent of it, rounded
+/// down so the creators never get more than the royalty
+pub fn creator_royalty(royalty: u64, share: u8) -> Result<u64, ProgramError> {
+    let amount = (royalty as u128)
+        .checked_mul(share as u128)
+        .map(|value| value / 100)
+        .ok_or(EscrowError::MathOverflow)?;
+    u64::try_from(amount).map_err(|_| EscrowError::MathOverflow.into())
+}
+
+/// Part of `cap` paid for a `royalty` when the royalties of a sale add up to
+/// `total_royalties`, more than `cap`. Rounded down so the capped royalties
+/// never exceed `cap`.
+pub fn capped_royalty(royalty: u64, total_royalties: u64, cap: u64) -> Result<u64, ProgramError> {
+    let amount = (royalty as u128)
+        .checked_mul(cap as u128)
+        .and_then(|value| value.checked_div(total_royalties as u128))
+        .ok_or(EscrowError::MathOverflow)?;
+    u64::try_from(amount).map_err(|_| EscrowError::MathOverflow.into())
+}
+
+/// Price of a dutch auction at `now`: `start_price` until `start_time`, then
+/// falling linearly to `end_price` at `end_time` and staying there. Rounded up
+/// in favour of the seller.
+pub fn dutch_auction_price(
+    start_price: u64,
+    end_price: u64,
+    start_time: i64,
+    end_time: i64,
+    now: i64,
+) -> Result<u64, ProgramError> {
+    if now <= start_time {
+        return Ok(start_price);
+    }
+    if now >= end_time {
+        return Ok(end_price);
+    }
+    let price_drop = start_price
+        .checked_sub(end_price)
+        .ok_or(EscrowError::MathOverflow)? as u128;
+    let elapsed = now
+        .checked_sub(start_time)
+        .ok_or(EscrowError::MathOverflow)? as u128;
+    let duration = end_time
+        .checked_sub(start_time)
+        .ok_or(EscrowError::MathOverflow)? as u128;
+    //* elapsed < duration here, so the drop so far is below price_drop
+    let dropped = price_drop
+        .checked_mul(elapsed)
+        .map(|value| value / duration)
+        .ok_or(EscrowError::MathOverflow)? as u64;
+    Ok(start_price - dropped)
+}
+
+/// Part of `platform_fee` owed to a holder of `share_bps` basis points of it,
+/// rounded down so the treasury keeps the dust
+pub fn fee_share(platform_fee: u64, share_bps: u64) -> Result<u64, ProgramError> {
+    let amount = (platform_fee as u128)
+        .checked_mul(share_bps as u128)
+        .map(|value| value / BPS_DENOMINATOR as u128)
+        .ok_or(EscrowError::MathOverflow)?;
+    u64::try_from(amount).map_err(|_| EscrowError::MathOverflow.into())
+}
+
+/// Part of `platform_fee` paid to a referrer holding `share_bps` basis points of it
+pub fn referral_amount(platform_fee: u64, share_bps: u64) -> Result<u64, ProgramError> {
+    fee_share(platform_fee, share_bps)
+}
+
+/// Rejects fees above the protocol maximum
+pub fn assert_valid_platform_fee(fee_bps: u64) -> Result<(), ProgramError> {
+    if fee_bps > MAX_PLATFORM_FEE_BPS {
+        return Err(EscrowError::FeeTooHigh.into());
+    }
+    Ok(())
+}
+
+/// Rejects referral shares above the whole platform fee
+pub fn assert_valid_referral_share(share_bps: u64) -> Result<(), ProgramError> {
+    if share_bps > BPS_DENOMINATOR {
+        return Err(EscrowError::InvalidReferralShare.into());
+    }
+    Ok(())
+}
@@ -1,51 +1,298 @@
-use solana_program::program_error::ProgramError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use spl_associated_token_account::get_associated_token_address;
 use std::convert::TryInto;
-// use borsh::{BorshDeserialize, BorshSerialize};
 
-// use crate::error::EscrowError::InvalidInstruction;
-// use std::io::Read;
+use crate::{
+    error::{EscrowError, EscrowError::InvalidInstruction},
+    order_book::{OrderBook, Side},
+    state::{
+        find_metadata_address, AuctionState, CollectionState, FeeRecipient, ListerState,
+        OfferState, ReferrerState, TokenState, MAX_FEE_RECIPIENTS, MAX_QUOTE_MINTS,
+    },
+};
+
+/// Version byte of Borsh encoded instructions.
+///
+/// Legacy instructions start with their tag (0-9), versioned ones start with
+/// this byte followed by the Borsh encoded `EscrowInstruction`. Versions are
+/// taken from the top of the byte range so they never collide with a legacy tag.
+pub const INSTRUCTION_VERSION: u8 = 0xFF;
+
+/// Platform config fields to change, `None` leaves the field untouched.
+///
+/// Encoded as a sequence of optional fields, each a presence byte followed by
+/// the value. New fields are appended at the end and missing trailing fields
+/// decode as `None`, so older clients keep working.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq, Clone)]
+pub struct PlatformConfigUpdate {
+    pub platform_fee_bps: Option<u64>,
+    pub treasury_account: Option<Pubkey>,
+    pub quote_mints: Option<[Pubkey; MAX_QUOTE_MINTS]>,
+    pub referral_share_bps: Option<u64>,
+    pub fee_recipients: Option<[FeeRecipient; MAX_FEE_RECIPIENTS]>,
+}
+
+/// Unit of a token amount passed to an instruction
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub enum AmountUnit {
+    BaseUnits,   // smallest unit of the mint
+    WholeTokens, // scaled by 10^decimals of the mint
+}
+
+impl AmountUnit {
+    /// Converts `amount` in this unit into base units of a mint with `decimals`
+    pub fn to_base_units(self, amount: u64, decimals: u8) -> Result<u64, ProgramError> {
+        match self {
+            AmountUnit::BaseUnits => Ok(amount),
+            AmountUnit::WholeTokens => 10u64
+                .checked_pow(decimals as u32)
+                .and_then(|scale| amount.checked_mul(scale))
+                .ok_or_else(|| EscrowError::MathOverflow.into()),
+        }
+    }
+}
+
+/// How an Exchange treats a listing holding fewer tokens than requested
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub enum FillMode {
+    Exact, // fill the full amount or fail
+    UpTo,  // fill as much as the listing holds, up to the amount
+}
+
+/// Price schedule of a dutch auction listing, the price falls linearly from
+/// `start_price` at `start_time` to `end_price` at `end_time` and then stays
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub struct DutchAuction {
+    pub start_price: u64, // quote base units per whole token
+    pub end_price: u64,   // quote base units per whole token, at most start_price
+    pub start_time: i64,  // unix timestamp
+    pub end_time: i64,    // unix timestamp, after start_time
+}
 
-#[derive(Debug, PartialEq)]
+/// Terms of an english auction listing, bids are in lamports for the whole
+/// listing
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub struct EnglishAuction {
+    pub reserve_price: u64,  // lowest first bid
+    pub min_increment: u64,  // a bid has to beat the highest bid by at least this
+    pub end_time: i64,       // unix timestamp bids close at
+    pub extension_secs: i64, // bids closer than this to the end extend it, 0 for never
+}
+
+/// Listing to buy from in a `BatchExchange`, its seller is passed in its
+/// account group
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub struct BatchFill {
+    pub listing_id: u64, // id of the listing of the seller
+    pub amount: u64,     // most tokens to buy from the listing
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+#[allow(clippy::large_enum_variant)]
 pub enum EscrowInstruction {
     CreatePlatformState {
-        amount: u64,
+        fee_bps: u64, // platform fee in basis points
     },
 
     CreateTokenstate, // List the new tokens for the trade
 
     ListToken {
         args: (u64, u64), //  NO of tokens to lsit and the prise of per token in the form of sol
+        unit: AmountUnit, // unit of the token amount, whole tokens when omitted
+        quote_mint: Pubkey, // mint the price is quoted in, default pubkey (SOL) when omitted
+        listing_id: u64,    // seller chosen id of the listing, 0 when omitted
+        expires_at: i64,    // unix timestamp after which the listing can't be filled, 0 for never
     },
 
     Exchange {
-        amount: u64, // No of tokens to buy
+        amount: u64,      // No of tokens to buy
+        unit: AmountUnit, // unit of the token amount, whole tokens when omitted
+        listing_id: u64,  // id of the listing to buy from, 0 when omitted
+        max_price_per_token: u64, // reject if the listing price is higher, no limit when omitted
+        max_total_price: u64,     // reject if the total to pay is higher, no limit when omitted
+        fill_mode: FillMode,      // exact when omitted
+    },
+
+    Cancel {
+        listing_id: u64, // cancle the listing, 0 when omitted
+    },
+
+    ProposeAdmin, // propose a new platform admin, who has to accept it with AcceptAdmin
+
+    AcceptAdmin, // the proposed admin takes over the platform authority
+
+    UpdatePlatformConfig {
+        config: PlatformConfigUpdate, // admin only, change platform config in place
+    },
+
+    UpdateListing {
+        price: Option<u64>,    // new price per token
+        deposit: Option<u64>,  // No of tokens to add to the listing
+        withdraw: Option<u64>, // No of tokens to take back from the listing
+        unit: AmountUnit,      // unit of the token amounts, whole tokens when omitted
+        listing_id: u64,       // id of the listing to update, 0 when omitted
+    },
+
+    CloseExpiredListing {
+        listing_id: u64, // permissionless, returns the tokens of an expired listing to the seller
+    },
+
+    SetRoyaltyEnforcement {
+        enforce_royalties: bool, // admin only, pay creator royalties on Exchange of the mint
+    },
+
+    AddCollection {
+        collection_mint: Pubkey, // admin only, allow NFT listings of this collection
+    },
+
+    RemoveCollection, // admin only, stop allowing new NFT listings of a collection
+
+    CreateNftTokenState, // permissionless, register an NFT mint of an allowed collection
+
+    MakeOffer {
+        token_amount: u64,    // No of tokens to buy
+        price_per_token: u64, // lamports per whole token, escrowed in the offer
+        unit: AmountUnit,     // unit of the token amount
+    },
+
+    CancelOffer, // the buyer takes back the escrowed lamports
+
+    AcceptOffer {
+        amount: u64,      // No of tokens to sell into the offer
+        unit: AmountUnit, // unit of the token amount
+    },
+
+    CreateOrderBook, // admin only, open the order book of a token state
+
+    PlaceOrder {
+        side: Side,           // bid to buy, ask to sell
+        price_per_token: u64, // limit price in lamports per whole token
+        amount: u64,          // No of tokens to trade
+        unit: AmountUnit,     // unit of the token amount
+    },
+
+    CancelOrder {
+        side: Side,    // side of the resting order
+        order_id: u64, // id of the resting order
+    },
+
+    ListDutchAuction {
+        token_amount: u64,     // No of tokens to list
+        auction: DutchAuction, // falling price schedule of the listing
+        unit: AmountUnit,      // unit of the token amount
+        quote_mint: Pubkey,    // mint the price is quoted in, default pubkey for SOL
+        listing_id: u64,       // seller chosen id of the listing
+        expires_at: i64,       // unix timestamp after which it can't be filled, 0 for never
+    },
+
+    ListEnglishAuction {
+        token_amount: u64,       // No of tokens to list
+        auction: EnglishAuction, // reserve, increment and timing of the auction
+        unit: AmountUnit,        // unit of the token amount
+        listing_id: u64,         // seller chosen id of the listing
+    },
+
+    PlaceBid {
+        bid: u64, // lamports for the whole listing
     },
 
-    Cancel, // cancle the listing
+    SettleAuction, // permissionless once the auction has ended
+
+    BatchExchange {
+        fills: Vec<BatchFill>,    // listings of the mint to buy from, cheapest first
+        unit: AmountUnit,         // unit of the token amounts
+        max_amount: u64,          // most tokens to buy over all the listings
+        max_price_per_token: u64, // listings priced higher are unfillable
+        max_total_price: u64,     // lamports the whole batch can spend
+        skip_unfillable: bool,    // skip unfillable listings instead of failing the batch
+    },
+
+    ListSwap {
+        token_amount: u64, // No of tokens to list
+        rate: u64,         // base units of the wanted mint per whole listed token
+        unit: AmountUnit,  // unit of the token amount
+        listing_id: u64,   // seller chosen id of the listing
+        expires_at: i64,   // unix timestamp after which it can't be filled, 0 for never
+    },
 }
 
 impl EscrowInstruction {
+    /// Packs a [EscrowInstruction](enum.EscrowInstruction.html) into the
+    /// versioned Borsh format.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = vec![INSTRUCTION_VERSION];
+        //* writing into a Vec can't fail
+        self.serialize(&mut buf).unwrap();
+        buf
+    }
+
     /// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
+    /// Accepts both the versioned Borsh format and the legacy tag format.
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (tag, rest) = input
-            .split_first()
-            .ok_or(ProgramError::InvalidInstructionData)?;
+        match input.split_first() {
+            Some((&INSTRUCTION_VERSION, rest)) => {
+                Self::try_from_slice(rest).map_err(|_| InvalidInstruction.into())
+            }
+            _ => Self::unpack_legacy(input),
+        }
+    }
+
+    /// Decodes the tag based format sent by clients predating `pack`
+    fn unpack_legacy(input: &[u8]) -> Result<Self, ProgramError> {
+        let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
 
         Ok(match tag {
             0 => Self::CreatePlatformState {
-                amount: Self::unpack_amount(rest)?,
+                fee_bps: Self::unpack_amount(rest)?,
             },
             1 => Self::CreateTokenstate,
 
             2 => Self::ListToken {
                 args: Self::unpack_data(rest)?,
+                unit: Self::unpack_unit(rest.get(16..).unwrap_or_default())?,
+                quote_mint: Self::unpack_quote_mint(rest.get(17..).unwrap_or_default())?,
+                listing_id: Self::unpack_listing_id(rest.get(49..).unwrap_or_default())?,
+                expires_at: Self::unpack_expires_at(rest.get(57..).unwrap_or_default())?,
             },
             3 => Self::Exchange {
                 amount: Self::unpack_amount(rest)?,
+                unit: Self::unpack_unit(rest.get(8..).unwrap_or_default())?,
+                listing_id: Self::unpack_listing_id(rest.get(9..).unwrap_or_default())?,
+                max_price_per_token: Self::unpack_limit(rest.get(17..).unwrap_or_default())?,
+                max_total_price: Self::unpack_limit(rest.get(25..).unwrap_or_default())?,
+                fill_mode: Self::unpack_fill_mode(rest.get(33..).unwrap_or_default())?,
+            },
+            4 => Self::Cancel {
+                listing_id: Self::unpack_listing_id(rest)?,
+            },
+            5 => Self::ProposeAdmin,
+            6 => Self::AcceptAdmin,
+            7 => Self::UpdatePlatformConfig {
+                config: Self::unpack_platform_config(rest)?,
+            },
+            8 => {
+                let (price, rest) = Self::unpack_option(rest, 8)?;
+                let (deposit, rest) = Self::unpack_option(rest, 8)?;
+                let (withdraw, rest) = Self::unpack_option(rest, 8)?;
+                Self::UpdateListing {
+                    price: price.map(Self::unpack_amount).transpose()?,
+                    deposit: deposit.map(Self::unpack_amount).transpose()?,
+                    withdraw: withdraw.map(Self::unpack_amount).transpose()?,
+                    unit: Self::unpack_unit(rest)?,
+                    listing_id: Self::unpack_listing_id(rest.get(1..).unwrap_or_default())?,
+                }
+            }
+            9 => Self::CloseExpiredListing {
+                listing_id: Self::unpack_amount(rest)?,
             },
-            4 => Self::Cancel,
 
-            _ => return Err(ProgramError::InvalidInstructionData),
+            _ => return Err(InvalidInstruction.into()),
         })
     }
 
@@ -54,22 +301,1146 @@ impl EscrowInstruction {
             .get(..8)
             .and_then(|slice| slice.try_into().ok())
             .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidInstructionData)?;
+            .ok_or(InvalidInstruction)?;
         Ok(amount)
     }
 
+    /// Legacy clients do not send a unit byte, their amounts are whole tokens
+    fn unpack_unit(input: &[u8]) -> Result<AmountUnit, ProgramError> {
+        match input.first() {
+            None | Some(1) => Ok(AmountUnit::WholeTokens),
+            Some(0) => Ok(AmountUnit::BaseUnits),
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+
+    /// Legacy clients do not send a quote mint, their listings are quoted in SOL
+    fn unpack_quote_mint(input: &[u8]) -> Result<Pubkey, ProgramError> {
+        match input.get(..32) {
+            Some(quote_mint) => Ok(Pubkey::new(quote_mint)),
+            None if input.is_empty() => Ok(Pubkey::default()),
+            None => Err(InvalidInstruction.into()),
+        }
+    }
+
+    /// Legacy clients do not send a listing id, their listings use id 0
+    fn unpack_listing_id(input: &[u8]) -> Result<u64, ProgramError> {
+        if input.is_empty() {
+            return Ok(0);
+        }
+        Self::unpack_amount(input)
+    }
+
+    /// Legacy clients do not send slippage limits, which means no limit
+    fn unpack_limit(input: &[u8]) -> Result<u64, ProgramError> {
+        if input.is_empty() {
+            return Ok(u64::MAX);
+        }
+        Self::unpack_amount(input)
+    }
+
+    fn unpack_fill_mode(input: &[u8]) -> Result<FillMode, ProgramError> {
+        match input.first() {
+            None | Some(0) => Ok(FillMode::Exact),
+            Some(1) => Ok(FillMode::UpTo),
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+
+    /// Listings without an expiry timestamp never expire
+    fn unpack_expires_at(input: &[u8]) -> Result<i64, ProgramError> {
+        if input.is_empty() {
+            return Ok(0);
+        }
+        let expires_at = input
+            .get(..8)
+            .and_then(|slice| slice.try_into().ok())
+            .map(i64::from_le_bytes)
+            .ok_or(InvalidInstruction)?;
+        Ok(expires_at)
+    }
+
     fn unpack_data(input: &[u8]) -> Result<(u64, u64), ProgramError> {
         let amount1 = input
             .get(0..8)
             .and_then(|slice| slice.try_into().ok())
             .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidInstructionData)?;
+            .ok_or(InvalidInstruction)?;
         let amount2 = input
             .get(8..16)
             .and_then(|slice| slice.try_into().ok())
             .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidInstructionData)?;
+            .ok_or(InvalidInstruction)?;
 
         Ok((amount1, amount2))
     }
+
+    fn unpack_platform_config(input: &[u8]) -> Result<PlatformConfigUpdate, ProgramError> {
+        let (platform_fee_bps, rest) = Self::unpack_option(input, 8)?;
+        let (treasury_account, rest) = Self::unpack_option(rest, 32)?;
+        let (quote_mints, rest) = Self::unpack_option(rest, 32 * MAX_QUOTE_MINTS)?;
+        let (referral_share_bps, rest) = Self::unpack_option(rest, 8)?;
+        let (fee_recipients, _rest) = Self::unpack_option(rest, 40 * MAX_FEE_RECIPIENTS)?;
+
+        Ok(PlatformConfigUpdate {
+            platform_fee_bps: platform_fee_bps.map(Self::unpack_amount).transpose()?,
+            treasury_account: treasury_account.map(Pubkey::new),
+            quote_mints: quote_mints.map(|src| {
+                let mut quote_mints = [Pubkey::default(); MAX_QUOTE_MINTS];
+                for (quote_mint, src) in quote_mints.iter_mut().zip(src.chunks(32)) {
+                    *quote_mint = Pubkey::new(src);
+                }
+                quote_mints
+            }),
+            referral_share_bps: referral_share_bps.map(Self::unpack_amount).transpose()?,
+            fee_recipients: fee_recipients
+                .map(|src| {
+                    let mut fee_recipients = [FeeRecipient::default(); MAX_FEE_RECIPIENTS];
+                    for (recipient, src) in fee_recipients.iter_mut().zip(src.chunks(40)) {
+                        recipient.account = Pubkey::new(&src[..32]);
+                        recipient.weight_bps = Self::unpack_amount(&src[32..])?;
+                    }
+                    Ok::<_, ProgramError>(fee_recipients)
+                })
+                .transpose()?,
+        })
+    }
+
+    /// Splits an optional field of `len` bytes off the front of `input`.
+    /// An exhausted input decodes as `None` so fields can be appended later.
+    fn unpack_option(input: &[u8], len: usize) -> Result<(Option<&[u8]>, &[u8]), ProgramError> {
+        match input.split_first() {
+            None => Ok((None, input)),
+            Some((0, rest)) => Ok((None, rest)),
+            Some((1, rest)) if rest.len() >= len => {
+                let (value, rest) = rest.split_at(len);
+                Ok((Some(value), rest))
+            }
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+}
+
+/// Accounts paying an Exchange of a listing quoted in an SPL token
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct QuoteAccounts {
+    pub quote_mint: Pubkey,
+    pub taker_token_account: Pubkey,    // pays the seller and the treasury
+    pub seller_token_account: Pubkey,   // owned by the seller
+    pub treasury_token_account: Pubkey, // owned by the platform treasury
+}
+
+/// Creates a `CreatePlatformState` instruction, `platform_state` is a new
+/// keypair account that has to sign next to the admin
+pub fn create_platform_state(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    platform_state: &Pubkey,
+    treasury_account: &Pubkey,
+    fee_bps: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(*platform_state, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*treasury_account, false),
+        ],
+        data: EscrowInstruction::CreatePlatformState { fee_bps }.pack(),
+    }
+}
+
+/// Creates a `CreateTokenstate` instruction registering `token_mint` on the platform
+pub fn create_token_state(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(*platform_state, false),
+            AccountMeta::new(token_state, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EscrowInstruction::CreateTokenstate.pack(),
+    }
+}
+
+/// Creates a `ListToken` instruction, `quote_mint` is the default pubkey for
+/// listings quoted in SOL. `collection_mint` is required for mints in NFT mode
+/// and names the allowed collection the NFT belongs to.
+#[allow(clippy::too_many_arguments)]
+pub fn list_token(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    token_amount: u64,
+    price_per_token: u64,
+    unit: AmountUnit,
+    quote_mint: &Pubkey,
+    listing_id: u64,
+    expires_at: i64,
+    collection_mint: Option<&Pubkey>,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: list_accounts(
+            program_id,
+            seller,
+            seller_token_account,
+            platform_state,
+            token_mint,
+            quote_mint,
+            listing_id,
+            collection_mint,
+        ),
+        data: EscrowInstruction::ListToken {
+            args: (token_amount, price_per_token),
+            unit,
+            quote_mint: *quote_mint,
+            listing_id,
+            expires_at,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `ListDutchAuction` instruction, same accounts as `list_token`
+#[allow(clippy::too_many_arguments)]
+pub fn list_dutch_auction(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    token_amount: u64,
+    auction: DutchAuction,
+    unit: AmountUnit,
+    quote_mint: &Pubkey,
+    listing_id: u64,
+    expires_at: i64,
+    collection_mint: Option<&Pubkey>,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: list_accounts(
+            program_id,
+            seller,
+            seller_token_account,
+            platform_state,
+            token_mint,
+            quote_mint,
+            listing_id,
+            collection_mint,
+        ),
+        data: EscrowInstruction::ListDutchAuction {
+            token_amount,
+            auction,
+            unit,
+            quote_mint: *quote_mint,
+            listing_id,
+            expires_at,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `ListSwap` instruction, the mint wanted in exchange followed by
+/// the accounts of a SOL quoted `list_token`. Takers fill it with an `exchange`
+/// quoted in `want_mint`, the fee goes to the associated token account of the
+/// treasury.
+#[allow(clippy::too_many_arguments)]
+pub fn list_swap(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    token_amount: u64,
+    want_mint: &Pubkey,
+    rate: u64,
+    unit: AmountUnit,
+    listing_id: u64,
+    expires_at: i64,
+    collection_mint: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![AccountMeta::new_readonly(*want_mint, false)];
+    accounts.extend(list_accounts(
+        program_id,
+        seller,
+        seller_token_account,
+        platform_state,
+        token_mint,
+        &Pubkey::default(),
+        listing_id,
+        collection_mint,
+    ));
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::ListSwap {
+            token_amount,
+            rate,
+            unit,
+            listing_id,
+            expires_at,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `ListEnglishAuction` instruction, the auction account followed
+/// by the accounts of a SOL quoted `list_token`
+#[allow(clippy::too_many_arguments)]
+pub fn list_english_auction(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    token_amount: u64,
+    auction: EnglishAuction,
+    unit: AmountUnit,
+    listing_id: u64,
+    collection_mint: Option<&Pubkey>,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (listing, _) =
+        ListerState::find_address(program_id, seller, token_mint, &token_state, listing_id);
+    let (auction_state, _) = AuctionState::find_address(program_id, &listing);
+
+    let mut accounts = vec![AccountMeta::new(auction_state, false)];
+    accounts.extend(list_accounts(
+        program_id,
+        seller,
+        seller_token_account,
+        platform_state,
+        token_mint,
+        &Pubkey::default(),
+        listing_id,
+        collection_mint,
+    ));
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::ListEnglishAuction {
+            token_amount,
+            auction,
+            unit,
+            listing_id,
+        }
+        .pack(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn list_accounts(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    listing_id: u64,
+    collection_mint: Option<&Pubkey>,
+) -> Vec<AccountMeta> {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (listing, _) =
+        ListerState::find_address(program_id, seller, token_mint, &token_state, listing_id);
+
+    let mut accounts = vec![
+        AccountMeta::new(*seller, true),
+        AccountMeta::new(*seller_token_account, false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new(listing, false),
+        AccountMeta::new(get_associated_token_address(&listing, token_mint), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(token_state, false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    if *quote_mint != Pubkey::default() || collection_mint.is_some() {
+        accounts.push(AccountMeta::new_readonly(*platform_state, false));
+    }
+    if let Some(collection_mint) = collection_mint {
+        let (collection_state, _) =
+            CollectionState::find_address(program_id, platform_state, collection_mint);
+        accounts.extend_from_slice(&[
+            AccountMeta::new_readonly(find_metadata_address(token_mint), false),
+            AccountMeta::new_readonly(collection_state, false),
+        ]);
+    }
+    accounts
+}
+
+/// Creates an `Exchange` instruction, `quote_accounts` is `None` when the
+/// listing is quoted in SOL. `seller_token_account` is a token account of the
+/// seller for the listed mint, receiving tokens sent to the escrow from outside
+/// when the fill closes the listing.
+///
+/// `royalty_accounts` is required when the token state enforces royalties and
+/// lists one account per verified creator of the mint metadata, in metadata
+/// order: the creator wallet for SOL listings, the creator's quote token
+/// account otherwise.
+///
+/// `fee_recipient_accounts` lists one account per fee recipient of the
+/// platform other than the treasury, in config order: the recipient wallet
+/// for SOL listings, the recipient's quote token account otherwise.
+///
+/// `referrer` is the wallet that routed the taker, paid its referral share of
+/// the platform fee: in lamports, or to its associated quote token account.
+/// The taker and the seller can't be the referrer.
+#[allow(clippy::too_many_arguments)]
+pub fn exchange(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    treasury_account: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    token_mint: &Pubkey,
+    listing_id: u64,
+    amount: u64,
+    unit: AmountUnit,
+    max_price_per_token: u64,
+    max_total_price: u64,
+    fill_mode: FillMode,
+    quote_accounts: Option<&QuoteAccounts>,
+    fee_recipient_accounts: &[Pubkey],
+    royalty_accounts: Option<&[Pubkey]>,
+    referrer: Option<&Pubkey>,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (listing, _) =
+        ListerState::find_address(program_id, seller, token_mint, &token_state, listing_id);
+
+    let mut accounts = vec![
+        AccountMeta::new(*taker, true),
+        AccountMeta::new_readonly(*platform_state, false),
+        AccountMeta::new(listing, false),
+        AccountMeta::new(get_associated_token_address(&listing, token_mint), false),
+        AccountMeta::new(*taker_token_account, false),
+        AccountMeta::new(*seller, false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new(token_state, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(*treasury_account, false),
+        AccountMeta::new(*seller_token_account, false),
+    ];
+    if let Some(quote_accounts) = quote_accounts {
+        accounts.extend_from_slice(&[
+            AccountMeta::new(quote_accounts.taker_token_account, false),
+            AccountMeta::new(quote_accounts.seller_token_account, false),
+            AccountMeta::new(quote_accounts.treasury_token_account, false),
+            AccountMeta::new_readonly(quote_accounts.quote_mint, false),
+        ]);
+    }
+    accounts.extend(
+        fee_recipient_accounts
+            .iter()
+            .map(|recipient| AccountMeta::new(*recipient, false)),
+    );
+    if let Some(royalty_accounts) = royalty_accounts {
+        accounts.push(AccountMeta::new_readonly(find_metadata_address(token_mint), false));
+        accounts.extend(
+            royalty_accounts
+                .iter()
+                .map(|creator| AccountMeta::new(*creator, false)),
+        );
+    }
+    if let Some(referrer) = referrer {
+        let quote_mint = quote_accounts.map_or(Pubkey::default(), |quote| quote.quote_mint);
+        let (referrer_state, _) =
+            ReferrerState::find_address(program_id, platform_state, referrer, &quote_mint);
+        accounts.extend_from_slice(&[
+            AccountMeta::new(*referrer, false),
+            AccountMeta::new(referrer_state, false),
+        ]);
+        if quote_accounts.is_some() {
+            accounts.push(AccountMeta::new(
+                get_associated_token_address(referrer, &quote_mint),
+                false,
+            ));
+        }
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Exchange {
+            amount,
+            unit,
+            listing_id,
+            max_price_per_token,
+            max_total_price,
+            fill_mode,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `Cancel` instruction returning the listed tokens to the seller
+pub fn cancel(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    listing_id: u64,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (listing, _) =
+        ListerState::find_address(program_id, seller, token_mint, &token_state, listing_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*seller, true),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new(*seller_token_account, false),
+            AccountMeta::new(get_associated_token_address(&listing, token_mint), false),
+            AccountMeta::new(listing, false),
+            AccountMeta::new(token_state, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: EscrowInstruction::Cancel { listing_id }.pack(),
+    }
+}
+
+/// Creates a `ProposeAdmin` instruction, proposing the default pubkey
+/// withdraws a pending proposal
+pub fn propose_admin(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    platform_state: &Pubkey,
+    new_admin: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(*platform_state, false),
+            AccountMeta::new_readonly(*new_admin, false),
+        ],
+        data: EscrowInstruction::ProposeAdmin.pack(),
+    }
+}
+
+/// Creates an `AcceptAdmin` instruction signed by the pending admin
+pub fn accept_admin(
+    program_id: &Pubkey,
+    new_admin: &Pubkey,
+    platform_state: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*new_admin, true),
+            AccountMeta::new(*platform_state, false),
+        ],
+        data: EscrowInstruction::AcceptAdmin.pack(),
+    }
+}
+
+/// Creates an `UpdatePlatformConfig` instruction
+pub fn update_platform_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    platform_state: &Pubkey,
+    config: PlatformConfigUpdate,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(*platform_state, false),
+        ],
+        data: EscrowInstruction::UpdatePlatformConfig { config }.pack(),
+    }
+}
+
+/// Creates an `UpdateListing` instruction, `None` leaves the field untouched
+#[allow(clippy::too_many_arguments)]
+pub fn update_listing(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    listing_id: u64,
+    price: Option<u64>,
+    deposit: Option<u64>,
+    withdraw: Option<u64>,
+    unit: AmountUnit,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (listing, _) =
+        ListerState::find_address(program_id, seller, token_mint, &token_state, listing_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*seller, true),
+            AccountMeta::new(*seller_token_account, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new(listing, false),
+            AccountMeta::new(get_associated_token_address(&listing, token_mint), false),
+            AccountMeta::new(token_state, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: EscrowInstruction::UpdateListing {
+            price,
+            deposit,
+            withdraw,
+            unit,
+            listing_id,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `CloseExpiredListing` instruction, any account can pay for it
+/// but the tokens always go to `seller_token_account`, owned by the seller
+pub fn close_expired_listing(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    listing_id: u64,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (listing, _) =
+        ListerState::find_address(program_id, seller, token_mint, &token_state, listing_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*seller, false),
+            AccountMeta::new(*seller_token_account, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new(listing, false),
+            AccountMeta::new(get_associated_token_address(&listing, token_mint), false),
+            AccountMeta::new(token_state, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: EscrowInstruction::CloseExpiredListing { listing_id }.pack(),
+    }
+}
+
+/// Creates a `SetRoyaltyEnforcement` instruction for the token state of `token_mint`
+pub fn set_royalty_enforcement(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    enforce_royalties: bool,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(*platform_state, false),
+            AccountMeta::new(token_state, false),
+        ],
+        data: EscrowInstruction::SetRoyaltyEnforcement { enforce_royalties }.pack(),
+    }
+}
+
+/// Creates an `AddCollection` instruction
+pub fn add_collection(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    platform_state: &Pubkey,
+    collection_mint: &Pubkey,
+) -> Instruction {
+    let (collection_state, _) =
+        CollectionState::find_address(program_id, platform_state, collection_mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(*platform_state, false),
+            AccountMeta::new(collection_state, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EscrowInstruction::AddCollection {
+            collection_mint: *collection_mint,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `RemoveCollection` instruction, the rent goes back to the admin
+pub fn remove_collection(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    platform_state: &Pubkey,
+    collection_mint: &Pubkey,
+) -> Instruction {
+    let (collection_state, _) =
+        CollectionState::find_address(program_id, platform_state, collection_mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(*platform_state, false),
+            AccountMeta::new(collection_state, false),
+        ],
+        data: EscrowInstruction::RemoveCollection.pack(),
+    }
+}
+
+/// Creates a `CreateNftTokenState` instruction, `payer` funds the token state.
+/// `collection_mint` names the allowed collection the NFT belongs to.
+pub fn create_nft_token_state(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    collection_mint: &Pubkey,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (collection_state, _) =
+        CollectionState::find_address(program_id, platform_state, collection_mint);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*platform_state, false),
+            AccountMeta::new(token_state, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(find_metadata_address(token_mint), false),
+            AccountMeta::new_readonly(collection_state, false),
+        ],
+        data: EscrowInstruction::CreateNftTokenState.pack(),
+    }
+}
+
+/// Creates a `MakeOffer` instruction escrowing the total price from `buyer`
+pub fn make_offer(
+    program_id: &Pubkey,
+    buyer: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    token_amount: u64,
+    price_per_token: u64,
+    unit: AmountUnit,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (offer, _) = OfferState::find_address(program_id, buyer, token_mint, &token_state);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*buyer, true),
+            AccountMeta::new(offer, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new_readonly(token_state, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: EscrowInstruction::MakeOffer {
+            token_amount,
+            price_per_token,
+            unit,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `CancelOffer` instruction
+pub fn cancel_offer(
+    program_id: &Pubkey,
+    buyer: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (offer, _) = OfferState::find_address(program_id, buyer, token_mint, &token_state);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*buyer, true),
+            AccountMeta::new(offer, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new_readonly(token_state, false),
+        ],
+        data: EscrowInstruction::CancelOffer.pack(),
+    }
+}
+
+/// Creates an `AcceptOffer` instruction, `fee_recipient_accounts` are the
+/// wallets of the platform fee recipients other than the treasury, in config
+/// order. `royalty_accounts` lists the verified creator wallets when the token
+/// state enforces royalties.
+#[allow(clippy::too_many_arguments)]
+pub fn accept_offer(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    buyer: &Pubkey,
+    buyer_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    treasury_account: &Pubkey,
+    token_mint: &Pubkey,
+    amount: u64,
+    unit: AmountUnit,
+    fee_recipient_accounts: &[Pubkey],
+    royalty_accounts: Option<&[Pubkey]>,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (offer, _) = OfferState::find_address(program_id, buyer, token_mint, &token_state);
+
+    let mut accounts = vec![
+        AccountMeta::new(*seller, true),
+        AccountMeta::new(*seller_token_account, false),
+        AccountMeta::new(*buyer, false),
+        AccountMeta::new(*buyer_token_account, false),
+        AccountMeta::new(offer, false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new_readonly(token_state, false),
+        AccountMeta::new_readonly(*platform_state, false),
+        AccountMeta::new(*treasury_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    accounts.extend(
+        fee_recipient_accounts
+            .iter()
+            .map(|recipient| AccountMeta::new(*recipient, false)),
+    );
+    if let Some(royalty_accounts) = royalty_accounts {
+        accounts.push(AccountMeta::new_readonly(find_metadata_address(token_mint), false));
+        accounts.extend(
+            royalty_accounts
+                .iter()
+                .map(|creator| AccountMeta::new(*creator, false)),
+        );
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::AcceptOffer { amount, unit }.pack(),
+    }
+}
+
+/// Creates a `CreateOrderBook` instruction, also creating the token vault of the book
+pub fn create_order_book(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (order_book, _) = OrderBook::find_address(program_id, &token_state);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(*platform_state, false),
+            AccountMeta::new_readonly(token_state, false),
+            AccountMeta::new(order_book, false),
+            AccountMeta::new(get_associated_token_address(&order_book, token_mint), false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: EscrowInstruction::CreateOrderBook.pack(),
+    }
+}
+
+/// Creates a `PlaceOrder` instruction.
+///
+/// `fee_recipient_accounts` are the wallets of the platform fee recipients
+/// other than the treasury, in config order.
+///
+/// `counterparties` lists the owners of the resting orders the new order
+/// crosses, best first, each followed by its associated token account. Bids
+/// filled by an ask are paid there and asks filled down to less than the
+/// minimum order get the rest of their tokens back there, created if missing.
+///
+/// `evicted_owner` is the owner of the worst order of the side when the side
+/// is full and the new order rests in its place.
+#[allow(clippy::too_many_arguments)]
+pub fn place_order(
+    program_id: &Pubkey,
+    trader: &Pubkey,
+    trader_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    treasury_account: &Pubkey,
+    token_mint: &Pubkey,
+    side: Side,
+    price_per_token: u64,
+    amount: u64,
+    unit: AmountUnit,
+    fee_recipient_accounts: &[Pubkey],
+    counterparties: &[Pubkey],
+    evicted_owner: Option<&Pubkey>,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (order_book, _) = OrderBook::find_address(program_id, &token_state);
+
+    let mut accounts = vec![
+        AccountMeta::new(*trader, true),
+        AccountMeta::new(*trader_token_account, false),
+        AccountMeta::new(order_book, false),
+        AccountMeta::new(get_associated_token_address(&order_book, token_mint), false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new_readonly(token_state, false),
+        AccountMeta::new_readonly(*platform_state, false),
+        AccountMeta::new(*treasury_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    accounts.extend(
+        fee_recipient_accounts
+            .iter()
+            .map(|recipient| AccountMeta::new(*recipient, false)),
+    );
+    //* an owner is followed by its associated token account when it receives tokens
+    let owner_accounts = |owner: &Pubkey, receives_tokens: bool| {
+        let mut owner_accounts = vec![AccountMeta::new(*owner, false)];
+        if receives_tokens {
+            owner_accounts.push(AccountMeta::new(
+                get_associated_token_address(owner, token_mint),
+                false,
+            ));
+        }
+        owner_accounts
+    };
+    for counterparty in counterparties {
+        accounts.extend(owner_accounts(counterparty, true));
+    }
+    if let Some(evicted_owner) = evicted_owner {
+        accounts.extend(owner_accounts(evicted_owner, side == Side::Ask));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::PlaceOrder {
+            side,
+            price_per_token,
+            amount,
+            unit,
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `CancelOrder` instruction refunding the rest of the order
+pub fn cancel_order(
+    program_id: &Pubkey,
+    trader: &Pubkey,
+    trader_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    token_mint: &Pubkey,
+    side: Side,
+    order_id: u64,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (order_book, _) = OrderBook::find_address(program_id, &token_state);
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*trader, true),
+            AccountMeta::new(*trader_token_account, false),
+            AccountMeta::new(order_book, false),
+            AccountMeta::new(get_associated_token_address(&order_book, token_mint), false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new_readonly(token_state, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: EscrowInstruction::CancelOrder { side, order_id }.pack(),
+    }
+}
+
+/// Creates a `PlaceBid` instruction, `previous_bidder` is the highest bidder
+/// to refund, `None` on the first bid
+pub fn place_bid(
+    program_id: &Pubkey,
+    bidder: &Pubkey,
+    listing: &Pubkey,
+    bid: u64,
+    previous_bidder: Option<&Pubkey>,
+) -> Instruction {
+    let (auction_state, _) = AuctionState::find_address(program_id, listing);
+
+    let mut accounts = vec![
+        AccountMeta::new(*bidder, true),
+        AccountMeta::new_readonly(*listing, false),
+        AccountMeta::new(auction_state, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(previous_bidder) = previous_bidder {
+        accounts.push(AccountMeta::new(*previous_bidder, false));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::PlaceBid { bid }.pack(),
+    }
+}
+
+/// Creates a `SettleAuction` instruction, `token_destination` is a token
+/// account of the highest bidder, or of the seller when there was no bid.
+/// `seller_token_account` receives tokens sent to the escrow from outside, and
+/// the auctioned tokens when the bid is refunded.
+/// `highest_bidder` is the wallet of the highest bidder, refunded when the bid
+/// can't be paid out past `AUCTION_REFUND_GRACE_SECS` after the end.
+/// `fee_recipient_accounts` are the wallets of the platform fee recipients
+/// other than the treasury, in config order.
+/// `royalty_accounts` are the creator wallets when the token state enforces
+/// royalties and there was a bid.
+#[allow(clippy::too_many_arguments)]
+pub fn settle_auction(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    seller_token_account: &Pubkey,
+    token_destination: &Pubkey,
+    highest_bidder: Option<&Pubkey>,
+    platform_state: &Pubkey,
+    treasury_account: &Pubkey,
+    token_mint: &Pubkey,
+    listing_id: u64,
+    fee_recipient_accounts: &[Pubkey],
+    royalty_accounts: Option<&[Pubkey]>,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+    let (listing, _) =
+        ListerState::find_address(program_id, seller, token_mint, &token_state, listing_id);
+    let (auction_state, _) = AuctionState::find_address(program_id, &listing);
+
+    let mut accounts = vec![
+        AccountMeta::new(*seller, false),
+        AccountMeta::new(*token_destination, false),
+        AccountMeta::new(listing, false),
+        AccountMeta::new(get_associated_token_address(&listing, token_mint), false),
+        AccountMeta::new(auction_state, false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new(token_state, false),
+        AccountMeta::new_readonly(*platform_state, false),
+        AccountMeta::new(*treasury_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(*seller_token_account, false),
+        //* without a bid there is no one to refund, the seller fills the slot
+        AccountMeta::new(*highest_bidder.unwrap_or(seller), false),
+    ];
+    accounts.extend(
+        fee_recipient_accounts
+            .iter()
+            .map(|recipient| AccountMeta::new(*recipient, false)),
+    );
+    if let Some(royalty_accounts) = royalty_accounts {
+        accounts.push(AccountMeta::new_readonly(find_metadata_address(token_mint), false));
+        accounts.extend(
+            royalty_accounts
+                .iter()
+                .map(|creator| AccountMeta::new(*creator, false)),
+        );
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SettleAuction.pack(),
+    }
+}
+
+/// Creates a `BatchExchange` instruction over SOL quoted listings of
+/// `token_mint`, `listings` are the seller, a token account of the seller
+/// and the fill of each listing. `fee_recipient_accounts` are the wallets of
+/// the platform fee recipients other than the treasury, in config order.
+/// `royalty_accounts` are the creator wallets when the token state enforces
+/// royalties.
+#[allow(clippy::too_many_arguments)]
+pub fn batch_exchange(
+    program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token_account: &Pubkey,
+    platform_state: &Pubkey,
+    treasury_account: &Pubkey,
+    token_mint: &Pubkey,
+    listings: &[(Pubkey, Pubkey, BatchFill)],
+    unit: AmountUnit,
+    max_amount: u64,
+    max_price_per_token: u64,
+    max_total_price: u64,
+    skip_unfillable: bool,
+    fee_recipient_accounts: &[Pubkey],
+    royalty_accounts: Option<&[Pubkey]>,
+) -> Instruction {
+    let (token_state, _) = TokenState::find_address(program_id, platform_state, token_mint);
+
+    let mut accounts = vec![
+        AccountMeta::new(*taker, true),
+        AccountMeta::new(*taker_token_account, false),
+        AccountMeta::new_readonly(*platform_state, false),
+        AccountMeta::new(*treasury_account, false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new(token_state, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.extend(
+        fee_recipient_accounts
+            .iter()
+            .map(|recipient| AccountMeta::new(*recipient, false)),
+    );
+    for (seller, seller_token_account, fill) in listings {
+        let (listing, _) = ListerState::find_address(
+            program_id,
+            seller,
+            token_mint,
+            &token_state,
+            fill.listing_id,
+        );
+        accounts.extend_from_slice(&[
+            AccountMeta::new(listing, false),
+            AccountMeta::new(get_associated_token_address(&listing, token_mint), false),
+            AccountMeta::new(*seller, false),
+            AccountMeta::new(*seller_token_account, false),
+        ]);
+    }
+    if let Some(royalty_accounts) = royalty_accounts {
+        accounts.push(AccountMeta::new_readonly(find_metadata_address(token_mint), false));
+        accounts.extend(
+            royalty_accounts
+                .iter()
+                .map(|creator| AccountMeta::new(*creator, false)),
+        );
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::BatchExchange {
+            fills: listings.iter().map(|(_, _, fill)| *fill).collect(),
+            unit,
+            max_amount,
+            max_price_per_token,
+            max_total_price,
+            skip_unfillable,
+        }
+        .pack(),
+    }
 }
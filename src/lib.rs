@@ -1,4 +1,8 @@
 pub mod entrypoint;
+pub mod error;
+pub mod fees;
 pub mod instruction;
+pub mod order_book;
 pub mod processor;
 pub mod state;
+pub mod validation;
@@ -0,0 +1,273 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+
+use crate::{error::EscrowError, fees::total_price};
+
+/// Resting orders each side of a book can hold
+pub const MAX_ORDERS_PER_SIDE: usize = 32;
+
+/// Smallest value of a placed order, in lamports
+pub const MIN_ORDER_LAMPORTS: u64 = 100_000;
+
+const ORDER_LEN: usize = 88;
+const SIDE_LEN: usize = MAX_ORDERS_PER_SIDE * ORDER_LEN;
+
+/// Side of an order
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub enum Side {
+    Bid, // buy tokens with lamports
+    Ask, // sell tokens for lamports
+}
+
+impl Side {
+    pub fn opposite(self) -> Self {
+        match self {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Order {
+    pub order_id: u64,         // increasing per book, gives the time priority
+    pub owner: Pubkey,         // receives the lamports of a filled ask, refunds of a cancelled bid
+    pub token_account: Pubkey, // token account the order was placed from
+    pub price_per_token: u64,  // lamports per whole token
+    pub quantity: u64,         // still open, in base units of the mint
+}
+
+impl Order {
+    fn unpack_from_slice(src: &[u8; ORDER_LEN]) -> Self {
+        let (order_id, owner, token_account, price_per_token, quantity) =
+            array_refs![src, 8, 32, 32, 8, 8];
+        Order {
+            order_id: u64::from_le_bytes(*order_id),
+            owner: Pubkey::new_from_array(*owner),
+            token_account: Pubkey::new_from_array(*token_account),
+            price_per_token: u64::from_le_bytes(*price_per_token),
+            quantity: u64::from_le_bytes(*quantity),
+        }
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8; ORDER_LEN]) {
+        let (order_id_dst, owner_dst, token_account_dst, price_per_token_dst, quantity_dst) =
+            mut_array_refs![dst, 8, 32, 32, 8, 8];
+        *order_id_dst = self.order_id.to_le_bytes();
+        owner_dst.copy_from_slice(self.owner.as_ref());
+        token_account_dst.copy_from_slice(self.token_account.as_ref());
+        *price_per_token_dst = self.price_per_token.to_le_bytes();
+        *quantity_dst = self.quantity.to_le_bytes();
+    }
+}
+
+/// Order book of a token state.
+///
+/// Each side is a fixed-size slab of orders kept sorted by price-time
+/// priority, so the best order of a side is always its first one: highest
+/// price first for bids, lowest price first for asks, oldest first on equal
+/// prices. The book PDA holds the lamports escrowed by resting bids, its
+/// associated token account the tokens escrowed by resting asks.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderBook {
+    pub is_initialized: bool,
+    pub token_state: Pubkey,
+    pub next_order_id: u64,
+    pub bids: Vec<Order>,
+    pub asks: Vec<Order>,
+}
+
+impl OrderBook {
+    /// Order book PDA of `token_state`, also the authority of the token vault
+    pub fn find_address(program_id: &Pubkey, token_state: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"order_book", token_state.as_ref()], program_id)
+    }
+
+    pub fn orders(&self, side: Side) -> &Vec<Order> {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    fn orders_mut(&mut self, side: Side) -> &mut Vec<Order> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+
+    /// Best resting order of `side`
+    pub fn best(&self, side: Side) -> Option<&Order> {
+        self.orders(side).first()
+    }
+
+    /// Whether an incoming `side` order at `price_per_token` crosses `resting`
+    pub fn crosses(side: Side, price_per_token: u64, resting: &Order) -> bool {
+        match side {
+            Side::Bid => resting.price_per_token <= price_per_token,
+            Side::Ask => resting.price_per_token >= price_per_token,
+        }
+    }
+
+    /// Rests a new order behind the orders of the same or a better price,
+    /// returning its order id
+    pub fn insert(
+        &mut self,
+        side: Side,
+        owner: Pubkey,
+        token_account: Pubkey,
+        price_per_token: u64,
+        quantity: u64,
+    ) -> Result<u64, ProgramError> {
+        let order_id = self.next_order_id;
+        let orders = self.orders_mut(side);
+        if orders.len() >= MAX_ORDERS_PER_SIDE {
+            return Err(EscrowError::OrderBookFull.into());
+        }
+
+        let position = orders
+            .iter()
+            .position(|order| match side {
+                Side::Bid => order.price_per_token < price_per_token,
+                Side::Ask => order.price_per_token > price_per_token,
+            })
+            .unwrap_or(orders.len());
+        orders.insert(
+            position,
+            Order {
+                order_id,
+                owner,
+                token_account,
+                price_per_token,
+                quantity,
+            },
+        );
+
+        self.next_order_id = order_id.checked_add(1).ok_or(EscrowError::MathOverflow)?;
+        Ok(order_id)
+    }
+
+    /// Makes room on a full `side` for an incoming order at `price_per_token` by
+    /// removing the worst resting order, only when the incoming price beats it.
+    /// Returns the evicted order, which the caller refunds.
+    pub fn evict_worst(
+        &mut self,
+        side: Side,
+        price_per_token: u64,
+    ) -> Result<Option<Order>, ProgramError> {
+        let orders = self.orders_mut(side);
+        if orders.len() < MAX_ORDERS_PER_SIDE {
+            return Ok(None);
+        }
+        let worst = orders.last().ok_or(EscrowError::OrderBookFull)?;
+        let beats_worst = match side {
+            Side::Bid => price_per_token > worst.price_per_token,
+            Side::Ask => price_per_token < worst.price_per_token,
+        };
+        if !beats_worst {
+            return Err(EscrowError::OrderBookFull.into());
+        }
+        Ok(orders.pop())
+    }
+
+    /// Takes `quantity` from the best order of `side`, removing it once empty.
+    /// A partly filled order left worth less than `MIN_ORDER_LAMPORTS` is removed
+    /// too and returned so its escrow can be refunded.
+    pub fn fill_best(
+        &mut self,
+        side: Side,
+        quantity: u64,
+        decimals: u8,
+    ) -> Result<Option<Order>, ProgramError> {
+        let orders = self.orders_mut(side);
+        let best = orders.first_mut().ok_or(EscrowError::OrderNotFound)?;
+        best.quantity = best
+            .quantity
+            .checked_sub(quantity)
+            .ok_or(EscrowError::MathOverflow)?;
+        if best.quantity == 0 {
+            orders.remove(0);
+        } else if total_price(best.quantity, best.price_per_token, decimals)? < MIN_ORDER_LAMPORTS {
+            return Ok(Some(orders.remove(0)));
+        }
+        Ok(None)
+    }
+
+    /// Removes the order `order_id` of `side`
+    pub fn remove(&mut self, side: Side, order_id: u64) -> Result<Order, ProgramError> {
+        let orders = self.orders_mut(side);
+        let position = orders
+            .iter()
+            .position(|order| order.order_id == order_id)
+            .ok_or(EscrowError::OrderNotFound)?;
+        Ok(orders.remove(position))
+    }
+}
+
+impl Sealed for OrderBook {}
+impl IsInitialized for OrderBook {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for OrderBook {
+    const LEN: usize = 43 + 2 * SIDE_LEN;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, OrderBook::LEN];
+        let (is_initialized, token_state, next_order_id, bid_count, ask_count, bids, asks) =
+            array_refs![src, 1, 32, 8, 1, 1, SIDE_LEN, SIDE_LEN];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let unpack_side = |orders: &[u8; SIDE_LEN], count: u8| {
+            let count = count as usize;
+            if count > MAX_ORDERS_PER_SIDE {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Ok(orders
+                .chunks_exact(ORDER_LEN)
+                .take(count)
+                .map(|order| Order::unpack_from_slice(array_ref![order, 0, ORDER_LEN]))
+                .collect::<Vec<_>>())
+        };
+        Ok(OrderBook {
+            is_initialized,
+            token_state: Pubkey::new_from_array(*token_state),
+            next_order_id: u64::from_le_bytes(*next_order_id),
+            bids: unpack_side(bids, bid_count[0])?,
+            asks: unpack_side(asks, ask_count[0])?,
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, OrderBook::LEN];
+        let (
+            is_initialized_dst,
+            token_state_dst,
+            next_order_id_dst,
+            bid_count_dst,
+            ask_count_dst,
+            bids_dst,
+            asks_dst,
+        ) = mut_array_refs![dst, 1, 32, 8, 1, 1, SIDE_LEN, SIDE_LEN];
+        is_initialized_dst[0] = self.is_initialized as u8;
+        token_state_dst.copy_from_slice(self.token_state.as_ref());
+        *next_order_id_dst = self.next_order_id.to_le_bytes();
+        bid_count_dst[0] = self.bids.len() as u8;
+        ask_count_dst[0] = self.asks.len() as u8;
+        for (orders, dst) in [(&self.bids, bids_dst), (&self.asks, asks_dst)] {
+            dst.fill(0);
+            for (order, order_dst) in orders.iter().zip(dst.chunks_exact_mut(ORDER_LEN)) {
+                order.pack_into_slice(array_mut_ref![order_dst, 0, ORDER_LEN]);
+            }
+        }
+    }
+}
@@ -7,14 +7,38 @@ use solana_program::{
     program_pack::Pack,
     pubkey::Pubkey,
     system_instruction::{create_account, transfer},
-    sysvar::rent::Rent,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
+use metaplex_token_metadata::state::Metadata;
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::slice::Iter;
+
 use crate::{
-    instruction::EscrowInstruction,
-    state::{ListerState, PlatformState, TokenState},
+    error::EscrowError,
+    fees::{
+        affordable_amount, assert_valid_platform_fee, assert_valid_referral_share, capped_royalty,
+        creator_royalty, fee_amount, referral_amount, split_fee, total_price,
+    },
+    instruction::{
+        AmountUnit, BatchFill, DutchAuction, EnglishAuction, EscrowInstruction, FillMode,
+        PlatformConfigUpdate,
+    },
+    order_book::{Order, OrderBook, Side, MIN_ORDER_LAMPORTS},
+    state::{
+        find_metadata_address, unpack_metadata, AuctionState, CollectionState, ListerState,
+        OfferState, PlatformState, ReferrerState, TokenState, AUCTION_REFUND_GRACE_SECS,
+    },
+    validation::{
+        assert_associated_token_program, assert_nft_mint, assert_owned_by, assert_referrer,
+        assert_rent_sysvar, assert_signer,
+        assert_system_program, assert_token_account, assert_token_account_mint,
+        assert_token_program, assert_writable,
+    },
 };
-use std::str::FromStr;
 pub struct Processor;
 impl Processor {
     pub fn process(
@@ -26,25 +50,208 @@ impl Processor {
 
         // Escrow instructions
         match instruction {
-            EscrowInstruction::CreatePlatformState { amount } => {
+            EscrowInstruction::CreatePlatformState { fee_bps } => {
                 msg!("Instruction: Update platform accounts");
-                Self::process_create_platform_state(accounts, amount, program_id)
+                Self::process_create_platform_state(accounts, fee_bps, program_id)
             }
             EscrowInstruction::CreateTokenstate => {
                 msg!("Instruction: Create Tokenstate accounts");
                 Self::process_create_token_state(accounts, program_id)
             }
-            EscrowInstruction::ListToken { args } => {
+            EscrowInstruction::ListToken {
+                args,
+                unit,
+                quote_mint,
+                listing_id,
+                expires_at,
+            } => {
                 msg!("Instruction: ListToken");
-                Self::process_init_escrow(accounts, args, program_id)
+                Self::process_init_escrow(
+                    accounts, args, unit, quote_mint, listing_id, expires_at, None, program_id,
+                )
+            }
+            EscrowInstruction::ListDutchAuction {
+                token_amount,
+                auction,
+                unit,
+                quote_mint,
+                listing_id,
+                expires_at,
+            } => {
+                msg!("Instruction: ListDutchAuction");
+                Self::process_init_escrow(
+                    accounts,
+                    (token_amount, auction.start_price),
+                    unit,
+                    quote_mint,
+                    listing_id,
+                    expires_at,
+                    Some(auction),
+                    program_id,
+                )
             }
-            EscrowInstruction::Exchange { amount } => {
+            EscrowInstruction::Exchange {
+                amount,
+                unit,
+                listing_id,
+                max_price_per_token,
+                max_total_price,
+                fill_mode,
+            } => {
                 msg!("Instruction: Exchange");
-                Self::process_exchange(accounts, amount, program_id)
+                Self::process_exchange(
+                    accounts,
+                    amount,
+                    unit,
+                    listing_id,
+                    max_price_per_token,
+                    max_total_price,
+                    fill_mode,
+                    program_id,
+                )
             }
-            EscrowInstruction::Cancel => {
+            EscrowInstruction::Cancel { listing_id } => {
                 msg!("Instruction: Cancel");
-                Self::process_cancel(accounts, program_id)
+                Self::process_cancel(accounts, listing_id, program_id)
+            }
+            EscrowInstruction::ProposeAdmin => {
+                msg!("Instruction: ProposeAdmin");
+                Self::process_propose_admin(accounts, program_id)
+            }
+            EscrowInstruction::AcceptAdmin => {
+                msg!("Instruction: AcceptAdmin");
+                Self::process_accept_admin(accounts, program_id)
+            }
+            EscrowInstruction::UpdatePlatformConfig { config } => {
+                msg!("Instruction: UpdatePlatformConfig");
+                Self::process_update_platform_config(accounts, config, program_id)
+            }
+            EscrowInstruction::UpdateListing {
+                price,
+                deposit,
+                withdraw,
+                unit,
+                listing_id,
+            } => {
+                msg!("Instruction: UpdateListing");
+                Self::process_update_listing(
+                    accounts, price, deposit, withdraw, unit, listing_id, program_id,
+                )
+            }
+            EscrowInstruction::CloseExpiredListing { listing_id } => {
+                msg!("Instruction: CloseExpiredListing");
+                Self::process_close_expired_listing(accounts, listing_id, program_id)
+            }
+            EscrowInstruction::SetRoyaltyEnforcement { enforce_royalties } => {
+                msg!("Instruction: SetRoyaltyEnforcement");
+                Self::process_set_royalty_enforcement(accounts, enforce_royalties, program_id)
+            }
+            EscrowInstruction::AddCollection { collection_mint } => {
+                msg!("Instruction: AddCollection");
+                Self::process_add_collection(accounts, collection_mint, program_id)
+            }
+            EscrowInstruction::RemoveCollection => {
+                msg!("Instruction: RemoveCollection");
+                Self::process_remove_collection(accounts, program_id)
+            }
+            EscrowInstruction::CreateNftTokenState => {
+                msg!("Instruction: CreateNftTokenState");
+                Self::process_create_nft_token_state(accounts, program_id)
+            }
+            EscrowInstruction::MakeOffer {
+                token_amount,
+                price_per_token,
+                unit,
+            } => {
+                msg!("Instruction: MakeOffer");
+                Self::process_make_offer(accounts, token_amount, price_per_token, unit, program_id)
+            }
+            EscrowInstruction::CancelOffer => {
+                msg!("Instruction: CancelOffer");
+                Self::process_cancel_offer(accounts, program_id)
+            }
+            EscrowInstruction::AcceptOffer { amount, unit } => {
+                msg!("Instruction: AcceptOffer");
+                Self::process_accept_offer(accounts, amount, unit, program_id)
+            }
+            EscrowInstruction::CreateOrderBook => {
+                msg!("Instruction: CreateOrderBook");
+                Self::process_create_order_book(accounts, program_id)
+            }
+            EscrowInstruction::PlaceOrder {
+                side,
+                price_per_token,
+                amount,
+                unit,
+            } => {
+                msg!("Instruction: PlaceOrder");
+                Self::process_place_order(accounts, side, price_per_token, amount, unit, program_id)
+            }
+            EscrowInstruction::CancelOrder { side, order_id } => {
+                msg!("Instruction: CancelOrder");
+                Self::process_cancel_order(accounts, side, order_id, program_id)
+            }
+            EscrowInstruction::ListEnglishAuction {
+                token_amount,
+                auction,
+                unit,
+                listing_id,
+            } => {
+                msg!("Instruction: ListEnglishAuction");
+                Self::process_list_english_auction(
+                    accounts,
+                    token_amount,
+                    auction,
+                    unit,
+                    listing_id,
+                    program_id,
+                )
+            }
+            EscrowInstruction::PlaceBid { bid } => {
+                msg!("Instruction: PlaceBid");
+                Self::process_place_bid(accounts, bid, program_id)
+            }
+            EscrowInstruction::ListSwap {
+                token_amount,
+                rate,
+                unit,
+                listing_id,
+                expires_at,
+            } => {
+                msg!("Instruction: ListSwap");
+                Self::process_list_swap(
+                    accounts,
+                    token_amount,
+                    rate,
+                    unit,
+                    listing_id,
+                    expires_at,
+                    program_id,
+                )
+            }
+            EscrowInstruction::SettleAuction => {
+                msg!("Instruction: SettleAuction");
+                Self::process_settle_auction(accounts, program_id)
+            }
+            EscrowInstruction::BatchExchange {
+                fills,
+                unit,
+                max_amount,
+                max_price_per_token,
+                max_total_price,
+                skip_unfillable,
+            } => {
+                msg!("Instruction: BatchExchange");
+                Self::process_batch_exchange(
+                    accounts,
+                    fills,
+                    unit,
+                    max_amount,
+                    max_price_per_token,
+                    max_total_price,
+                    skip_unfillable,
+                    program_id,
+                )
             }
         }
     }
@@ -52,23 +259,17 @@ impl Processor {
     //* Create a platform state
     pub fn process_create_platform_state(
         accounts: &[AccountInfo],
-        amount: u64,
+        fee_bps: u64,
         program_id: &Pubkey,
     ) -> ProgramResult {
-        //*update authority of platform
-        let admin_update_auth =
-            Pubkey::from_str("J8AjdAYf9jji6c8bnH56hwNHtdzovvJMjVmMBeDYY8uZ").unwrap();
+        assert_valid_platform_fee(fee_bps)?;
 
         let account_info_iter = &mut accounts.iter();
 
         let admin = next_account_info(account_info_iter)?; // admin account key
 
-        //* validation check if the user calling this instruction
-        //** actually holds the authority for updating the platform account
-        if admin_update_auth != *admin.key {
-            msg!("Invalid admin.....");
-            return Err(ProgramError::InvalidAccountData);
-        }
+        //* the creator of the platform becomes its admin
+        assert_signer(admin, EscrowError::AdminNotSigner)?;
 
         let platfrom_account = next_account_info(account_info_iter)?; // platform state account
 
@@ -76,6 +277,9 @@ impl Processor {
 
         let treasury_acc = next_account_info(account_info_iter)?; // treasury_acc account key
 
+        assert_writable(platfrom_account)?;
+        assert_system_program(system_program)?;
+
         //* Create a new account for platform state*/
         invoke(
             &create_account(
@@ -93,9 +297,7 @@ impl Processor {
         )?;
 
         //* check if program owns platfrom_account account
-        if platfrom_account.owner != program_id {
-            return Err(ProgramError::IncorrectProgramId);
-        }
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
 
         //* unpack the platfrom_account state, to store data into
         let mut account_update_info =
@@ -103,7 +305,10 @@ impl Processor {
 
         account_update_info.is_initialized = true;
         account_update_info.treasury_account = *treasury_acc.key;
-        account_update_info.platform_fess = amount;
+        account_update_info.platform_fee_bps = fee_bps;
+        account_update_info.admin = *admin.key;
+        account_update_info.pending_admin = Pubkey::default();
+        account_update_info.referral_share_bps = 0;
 
         //* pack data into the platform account
         PlatformState::pack(
@@ -120,27 +325,19 @@ impl Processor {
         accounts: &[AccountInfo],
         program_id: &Pubkey,
     ) -> ProgramResult {
-        //*update authority of platform
-        let admin_update_auth =
-            Pubkey::from_str("J8AjdAYf9jji6c8bnH56hwNHtdzovvJMjVmMBeDYY8uZ").unwrap();
-
         let account_info_iter = &mut accounts.iter();
 
         let admin = next_account_info(account_info_iter)?; // admin account key
 
-        //* validation check if the user calling this instruction
-        //** actually holds the authority for updating the platform account
-        if admin_update_auth != *admin.key {
-            msg!("Invalid admin.....");
-            return Err(ProgramError::InvalidAccountData);
-        }
-
         let platfrom_account = next_account_info(account_info_iter)?; // platform state account
 
         //* check if program owns platfrom_account account
-        if platfrom_account.owner != program_id {
-            return Err(ProgramError::IncorrectProgramId);
-        }
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
+
+        //* validation check if the user calling this instruction
+        //** actually holds the authority for updating the platform account
+        let platform_info = PlatformState::unpack(&platfrom_account.try_borrow_data()?)?;
+        Self::assert_admin(admin, &platform_info)?;
 
         let token_state_account = next_account_info(account_info_iter)?; // token state account
 
@@ -148,68 +345,79 @@ impl Processor {
 
         let system_program = next_account_info(account_info_iter)?; // system_program account
 
-
-        let (token_pda, nonce) = Pubkey::find_program_address(
-            &[platfrom_account.key.as_ref(), token_mint.key.as_ref()],
+        Self::create_token_state_account(
+            admin,
+            platfrom_account,
+            token_state_account,
+            token_mint,
+            system_program,
+            false,
             program_id,
-        ); // Create pda for token state account
+        )
+    }
 
-        if token_pda != *token_state_account.key {
-            return Err(ProgramError::InvalidAccountData);
-        }
+    //* Permissionless, register an NFT mint of an allowed collection. Offers, the
+    //* order book and listings all need the token state, so mints outside the
+    //* allowed collections can't be traded at all.
+    pub fn process_create_nft_token_state(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
 
-        //* Create a new account for token state*/
-        invoke_signed(
-            &create_account(
-                admin.key,
-                token_state_account.key,
-                Rent::default().minimum_balance(TokenState::LEN),
-                TokenState::LEN as u64,
-                program_id,
-            ),
-            &[
-                admin.clone(),
-                token_state_account.clone(),
-                system_program.clone(),
-            ],
-            &[&[
-                (platfrom_account.key).as_ref(),
-                (token_mint.key).as_ref(),
-                &[nonce],
-            ]],
-        )?;
+        let payer = next_account_info(account_info_iter)?; // pays the rent of the token state
 
-        if token_state_account.owner != program_id {
-            return Err(ProgramError::IncorrectProgramId);
-        }
+        assert_signer(payer, EscrowError::PayerNotSigner)?;
 
-        //* unpack the platfrom_account state, to store data into
-        let mut token_info = TokenState::unpack_unchecked(&token_state_account.try_borrow_data()?)?;
+        let platfrom_account = next_account_info(account_info_iter)?; // platform state account
 
-        token_info.is_initialized = true;
-        token_info.owner_pubkey = *admin.key;
-        token_info.total_no_of_tokens_listed = 0;
-        token_info.token_mint = *token_mint.key;
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
 
-        //* pack data into the platform account
-        TokenState::pack(token_info, &mut token_state_account.try_borrow_mut_data()?)?;
+        let token_state_account = next_account_info(account_info_iter)?; // token state account
 
-        msg!("Token_state_info : {:?}", token_info);
+        let token_mint = next_account_info(account_info_iter)?; // NFT mint
 
-        Ok(())
+        let system_program = next_account_info(account_info_iter)?; // system_program account
+
+        let metadata_account = next_account_info(account_info_iter)?; // NFT metadata account
+
+        let collection_state_account = next_account_info(account_info_iter)?; // collection state
+
+        assert_nft_mint(token_mint)?;
+        Self::assert_collection_allowed(
+            platfrom_account,
+            token_mint.key,
+            metadata_account,
+            collection_state_account,
+            program_id,
+        )?;
+
+        Self::create_token_state_account(
+            payer,
+            platfrom_account,
+            token_state_account,
+            token_mint,
+            system_program,
+            true,
+            program_id,
+        )
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn process_init_escrow(
         accounts: &[AccountInfo],
         args: (u64, u64),
+        unit: AmountUnit,
+        quote_mint: Pubkey,
+        listing_id: u64,
+        expires_at: i64,
+        auction: Option<DutchAuction>,
         program_id: &Pubkey,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let initializer = next_account_info(account_info_iter)?;
         // initializer is signer validation check
-        if !initializer.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
-        }
+        assert_signer(initializer, EscrowError::SellerNotSigner)?;
         let intializer_token_account = next_account_info(account_info_iter)?;
 
         let token_mint = next_account_info(account_info_iter)?;
@@ -224,15 +432,71 @@ impl Processor {
 
         let token_state_account = next_account_info(account_info_iter)?;
 
+        let associated_token_program = next_account_info(account_info_iter)?;
+
+        let rent_sysvar = next_account_info(account_info_iter)?;
+
+        assert_writable(pda_account)?;
+        assert_writable(pda_token_account)?;
+        assert_writable(token_state_account)?;
+        assert_token_program(token_program)?;
+        assert_system_program(system_program)?;
+        assert_associated_token_program(associated_token_program)?;
+        assert_rent_sysvar(rent_sysvar)?;
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let mut token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+
+        //* SPL quoted and NFT listings pass the platform state to check the quote
+        //* mint and the collection against
+        if quote_mint != Pubkey::default() || token_state_info.nft_mode {
+            let platform_state_account = next_account_info(account_info_iter)?;
+
+            assert_owned_by(
+                platform_state_account,
+                program_id,
+                EscrowError::PlatformStateNotOwnedByProgram,
+            )?;
+            let (token_pda, _nonce) =
+                TokenState::find_address(program_id, platform_state_account.key, token_mint.key);
+            if token_pda != *token_state_account.key {
+                return Err(EscrowError::InvalidTokenStatePda.into());
+            }
+
+            let platform_info = PlatformState::unpack(&platform_state_account.try_borrow_data()?)?;
+            if !platform_info.is_quote_mint_allowed(&quote_mint) {
+                return Err(EscrowError::QuoteMintNotAllowed.into());
+            }
+
+            if token_state_info.nft_mode {
+                let metadata_account = next_account_info(account_info_iter)?;
+                let collection_state_account = next_account_info(account_info_iter)?;
+
+                assert_nft_mint(token_mint)?;
+                Self::assert_collection_allowed(
+                    platform_state_account,
+                    token_mint.key,
+                    metadata_account,
+                    collection_state_account,
+                    program_id,
+                )?;
+            }
+        }
 
         //* get a pda for escrow program
-        let (pda, nonce) = Pubkey::find_program_address(
-            &[initializer.key.as_ref(), token_mint.key.as_ref(),token_state_account.key.as_ref()],
+        let (pda, nonce) = ListerState::find_address(
             program_id,
+            initializer.key,
+            token_mint.key,
+            token_state_account.key,
+            listing_id,
         );
 
         if pda != *pda_account.key {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(EscrowError::InvalidListingPda.into());
         }
 
         //* Create a new account for Escrow
@@ -254,36 +518,81 @@ impl Processor {
                     (initializer.key).as_ref(),
                     (token_mint.key).as_ref(),
                     (token_state_account.key).as_ref(),
+                    &listing_id.to_le_bytes(),
                     &[nonce],
                 ]],
             )?;
+        }
 
-            //*  Unpack escrow_account state
-            let mut lister_info = ListerState::unpack_unchecked(&pda_account.try_borrow_data()?)?;
+        let mut lister_info = ListerState::unpack_unchecked(&pda_account.try_borrow_data()?)?;
 
-            lister_info.is_initialized = false;
+        if lister_info.is_initialized {
+            return Err(EscrowError::ListingAlreadyActive.into());
+        }
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+        if expires_at != 0 && expires_at <= Clock::get()?.unix_timestamp {
+            return Err(EscrowError::InvalidExpiry.into());
+        }
+        //* the auction price only ever falls, over a non empty window
+        if let Some(auction) = auction {
+            if auction.start_price < auction.end_price
+                || auction.start_time < 0
+                || auction.end_time <= auction.start_time
+            {
+                return Err(EscrowError::InvalidAuction.into());
+            }
         }
 
-        let mut lister_info = ListerState::unpack_unchecked(&pda_account.try_borrow_data()?)?;
-        let mut token_state_info = TokenState::unpack_unchecked(&token_state_account.try_borrow_data()?)?;
+        let token_amount = unit.to_base_units(args.0, token_state_info.decimals)?;
+        if token_state_info.nft_mode && token_amount != 1 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
 
-        if lister_info.is_initialized == true {
-            return Err(ProgramError::AccountAlreadyInitialized);
+        //* the escrow is the associated token account of the listing pda, created
+        //* by the program so nobody else can hold authority over it
+        if *pda_token_account.key != get_associated_token_address(pda_account.key, token_mint.key) {
+            return Err(EscrowError::InvalidEscrowTokenAccount.into());
         }
+        if pda_token_account.data_is_empty() {
+            invoke(
+                &create_associated_token_account(initializer.key, pda_account.key, token_mint.key),
+                &[
+                    initializer.clone(),
+                    pda_token_account.clone(),
+                    pda_account.clone(),
+                    token_mint.clone(),
+                    system_program.clone(),
+                    token_program.clone(),
+                    rent_sysvar.clone(),
+                    associated_token_program.clone(),
+                ],
+            )?;
+        }
+        assert_token_account(
+            pda_token_account,
+            pda_account.key,
+            token_mint.key,
+            EscrowError::InvalidEscrowTokenAccount,
+        )?;
 
         //* Transfer token amount from initializer to pda
-        let tranfer_instructions = spl_token::instruction::transfer(
+        let tranfer_instructions = spl_token::instruction::transfer_checked(
             token_program.key,
             intializer_token_account.key,
+            token_mint.key,
             pda_token_account.key,
             initializer.key,
             &[],
-            args.0 * 100,
+            token_amount,
+            token_state_info.decimals,
         )?;
         invoke(
             &tranfer_instructions,
             &[
                 intializer_token_account.clone(),
+                token_mint.clone(),
                 pda_token_account.clone(),
                 initializer.clone(),
                 token_program.clone(),
@@ -296,10 +605,26 @@ impl Processor {
         lister_info.token_mint = *token_mint.key;
 
         lister_info.token_account_pubkey = *pda_token_account.key;
-        lister_info.token_amount = args.0;
+        lister_info.token_amount = token_amount;
         lister_info.expected_amount_per_token = args.1;
-
-        token_state_info.total_no_of_tokens_listed=args.0;
+        lister_info.quote_mint = quote_mint;
+        lister_info.listing_id = listing_id;
+        lister_info.expires_at = expires_at;
+        //* fixed price listings keep a zeroed schedule
+        let auction = auction.unwrap_or(DutchAuction {
+            start_price: args.1,
+            end_price: 0,
+            start_time: 0,
+            end_time: 0,
+        });
+        lister_info.auction_end_price = auction.end_price;
+        lister_info.auction_start_time = auction.start_time;
+        lister_info.auction_end_time = auction.end_time;
+
+        token_state_info.total_no_of_tokens_listed = token_state_info
+            .total_no_of_tokens_listed
+            .checked_add(token_amount)
+            .ok_or(EscrowError::MathOverflow)?;
         TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
 
         ListerState::pack(lister_info, &mut pda_account.try_borrow_mut_data()?)?;
@@ -307,9 +632,15 @@ impl Processor {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn process_exchange(
         accounts: &[AccountInfo],
         expected_token_amount_by_taker: u64,
+        unit: AmountUnit,
+        listing_id: u64,
+        max_price_per_token: u64,
+        max_total_price: u64,
+        fill_mode: FillMode,
         program_id: &Pubkey,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
@@ -317,15 +648,15 @@ impl Processor {
         let taker = next_account_info(account_info_iter)?;
 
         //* check if the buyer is the singer for this instruction
-        if !taker.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
-        }
+        assert_signer(taker, EscrowError::TakerNotSigner)?;
         let platform_state_account = next_account_info(account_info_iter)?;
 
         //* check if owner of platform account is the program
-        if *platform_state_account.owner != *program_id {
-            return Err(ProgramError::IncorrectProgramId);
-        }
+        assert_owned_by(
+            platform_state_account,
+            program_id,
+            EscrowError::PlatformStateNotOwnedByProgram,
+        )?;
 
         let pda_account = next_account_info(account_info_iter)?;
 
@@ -340,47 +671,117 @@ impl Processor {
         let token_state_account = next_account_info(account_info_iter)?;
 
 
-        let (pda, nonce) = Pubkey::find_program_address(
-            &[
-                initializers_main_account.key.as_ref(),
-                token_mint.key.as_ref(),
-                token_state_account.key.as_ref(),
-            ],
+        let (pda, nonce) = ListerState::find_address(
             program_id,
+            initializers_main_account.key,
+            token_mint.key,
+            token_state_account.key,
+            listing_id,
         );
 
         if pda != *pda_account.key {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(EscrowError::InvalidListingPda.into());
         }
         //* check if owner of escrow account is the program
-        if pda_account.owner != program_id {
-            return Err(ProgramError::IncorrectProgramId);
-        }
+        assert_owned_by(pda_account, program_id, EscrowError::ListingNotOwnedByProgram)?;
         let mut lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+        //* english auctions are only sold through SettleAuction
+        if lister_info.english_auction {
+            return Err(EscrowError::EnglishAuctionListing.into());
+        }
         let paltform_info = PlatformState::unpack(&platform_state_account.try_borrow_data()?)?;
+
+        //* stale listings can only be closed, not filled
+        let now = Clock::get()?.unix_timestamp;
+        if lister_info.is_expired(now) {
+            return Err(EscrowError::ListingExpired.into());
+        }
+        //* the token state has to belong to the platform the fees are read from
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let (token_pda, _nonce) =
+            TokenState::find_address(program_id, platform_state_account.key, token_mint.key);
+        if token_pda != *token_state_account.key {
+            return Err(EscrowError::InvalidTokenStatePda.into());
+        }
         let mut token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
 
-        let amount_per_token = lister_info.expected_amount_per_token;
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+        assert_token_account_mint(
+            takers_token_account,
+            token_mint.key,
+            EscrowError::InvalidTakerTokenAccount,
+        )?;
+        let expected_token_amount_by_taker =
+            unit.to_base_units(expected_token_amount_by_taker, token_state_info.decimals)?;
+
+        //* buy the exact amount, or as much as the listing still holds
+        let expected_token_amount_by_taker = match fill_mode {
+            FillMode::Exact => {
+                if expected_token_amount_by_taker > lister_info.token_amount {
+                    return Err(EscrowError::InsufficientListedTokens.into());
+                }
+                expected_token_amount_by_taker
+            }
+            FillMode::UpTo => expected_token_amount_by_taker.min(lister_info.token_amount),
+        };
+        if expected_token_amount_by_taker == 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+        //* NFT listings are sold whole
+        if token_state_info.nft_mode && expected_token_amount_by_taker != lister_info.token_amount {
+            return Err(EscrowError::PartialFillNotAllowed.into());
+        }
+        msg!("token amount filled {}", expected_token_amount_by_taker);
+
+        //* dutch auctions are priced from the clock, fixed listings at their price
+        let amount_per_token = lister_info.current_price(now)?;
         msg!("amount_per_token {}",amount_per_token);
 
-        let total_sol_amount_of_tokens = expected_token_amount_by_taker * amount_per_token;
+        //* protect the buyer against a price change before execution
+        if amount_per_token > max_price_per_token {
+            return Err(EscrowError::PriceAboveLimit.into());
+        }
+
+        let total_sol_amount_of_tokens = total_price(
+            expected_token_amount_by_taker,
+            amount_per_token,
+            token_state_info.decimals,
+        )?;
 
         msg!("total_sol_amount_of_tokens {}",total_sol_amount_of_tokens);
 
+        if total_sol_amount_of_tokens > max_total_price {
+            return Err(EscrowError::TotalPriceAboveLimit.into());
+        }
 
-        let platform_fee = (total_sol_amount_of_tokens * paltform_info.platform_fess) / 100;
+        //* fee is rounded up, the seller gets the remainder
+        let (platform_fee, amount_expected_by_seller) =
+            split_fee(total_sol_amount_of_tokens, paltform_info.platform_fee_bps)?;
 
         msg!("platform_fee {}",platform_fee);
 
 
-        let amount_expected_by_seller = total_sol_amount_of_tokens - platform_fee;
-
         msg!("amount_expected_by_seller {}",amount_expected_by_seller);
 
 
         if lister_info.seller_pubkey != *initializers_main_account.key {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(EscrowError::SellerMismatch.into());
+        }
+        if lister_info.token_account_pubkey != *pdas_token_account.key {
+            return Err(EscrowError::EscrowTokenAccountMismatch.into());
         }
+        assert_token_account(
+            pdas_token_account,
+            pda_account.key,
+            token_mint.key,
+            EscrowError::InvalidEscrowTokenAccount,
+        )?;
 
         let token_program = next_account_info(account_info_iter)?;
         let system_program = next_account_info(account_info_iter)?;
@@ -388,60 +789,254 @@ impl Processor {
         //* platform team and treasury accounts
         let platform_treasury = next_account_info(account_info_iter)?;
 
+        //* receives what is left in the escrow when the listing is closed
+        let sellers_token_account = next_account_info(account_info_iter)?;
+
+        assert_token_program(token_program)?;
+        assert_system_program(system_program)?;
+        assert_writable(pda_account)?;
+        assert_writable(pdas_token_account)?;
+        assert_writable(takers_token_account)?;
+        assert_writable(initializers_main_account)?;
+        assert_writable(token_state_account)?;
+        assert_writable(platform_treasury)?;
+
 
         //* validation checks for treasury and team accounts
         if paltform_info.treasury_account != *platform_treasury.key {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(EscrowError::InvalidTreasuryAccount.into());
         }
 
-        //* transer SOL to initializers_main_account
-        invoke(
-            &transfer(
-                taker.key,
-                initializers_main_account.key,
-                amount_expected_by_seller,
-            ),
-            &[
-                taker.clone(),
-                initializers_main_account.clone(),
-                taker.clone(),
-                system_program.clone(),
-            ],
+        //* SPL quoted listings pay seller, treasury and creators in the quote mint
+        let quote_accounts = if lister_info.quote_mint == Pubkey::default() {
+            None
+        } else {
+            let takers_quote_token_account = next_account_info(account_info_iter)?;
+            let sellers_quote_token_account = next_account_info(account_info_iter)?;
+            let treasury_quote_token_account = next_account_info(account_info_iter)?;
+            let quote_mint = next_account_info(account_info_iter)?;
+
+            if lister_info.quote_mint != *quote_mint.key {
+                return Err(EscrowError::QuoteMintMismatch.into());
+            }
+            //* swaps can want any mint, their fee goes to the associated token
+            //* account of the treasury
+            if lister_info.swap {
+                let treasury_token_address = get_associated_token_address(
+                    &paltform_info.treasury_account,
+                    quote_mint.key,
+                );
+                if treasury_token_address != *treasury_quote_token_account.key {
+                    return Err(EscrowError::InvalidQuoteTokenAccount.into());
+                }
+            } else if !paltform_info.is_quote_mint_allowed(quote_mint.key) {
+                return Err(EscrowError::QuoteMintNotAllowed.into());
+            }
+            assert_token_account(
+                sellers_quote_token_account,
+                &lister_info.seller_pubkey,
+                quote_mint.key,
+                EscrowError::InvalidQuoteTokenAccount,
+            )?;
+            assert_token_account(
+                treasury_quote_token_account,
+                &paltform_info.treasury_account,
+                quote_mint.key,
+                EscrowError::InvalidQuoteTokenAccount,
+            )?;
+            let quote_decimals = Mint::unpack(&quote_mint.try_borrow_data()?)
+                .map_err(|_| EscrowError::InvalidMint)?
+                .decimals;
+
+            Some((
+                takers_quote_token_account,
+                sellers_quote_token_account,
+                treasury_quote_token_account,
+                quote_mint,
+                quote_decimals,
+            ))
+        };
+
+        //* the secondary fee recipients of the platform come before the creators
+        let fee_recipient_accounts = Self::fee_recipient_accounts(
+            account_info_iter,
+            &paltform_info,
+            quote_accounts.map(|(_, _, _, quote_mint, _)| quote_mint.key),
         )?;
-        //* transer platform fees to treasury_account
-        msg!("1");
 
-        invoke(
-            &transfer(taker.key, platform_treasury.key, platform_fee),
-            &[
-                taker.clone(),
-                platform_treasury.clone(),
-                taker.clone(),
-                system_program.clone(),
-            ],
-        )?;
-        msg!("2");
+        //* creator royalties are paid out of the seller's share
+        let royalties = if token_state_info.enforce_royalties {
+            Self::creator_royalties(
+                account_info_iter,
+                token_mint.key,
+                quote_accounts.map(|(_, _, _, quote_mint, _)| quote_mint.key),
+                total_sol_amount_of_tokens,
+            )?
+        } else {
+            vec![]
+        };
+        let amount_expected_by_seller =
+            Self::seller_proceeds(amount_expected_by_seller, &royalties)?;
+
+        //* an optional referrer passed after the creators earns a share of the
+        //* platform fee, paid in the quote of the listing
+        let referral = match account_info_iter.next() {
+            Some(referrer) => {
+                assert_referrer(referrer, taker.key, &lister_info.seller_pubkey)?;
+                let referrer_state_account = next_account_info(account_info_iter)?;
+                let referrer_payout_account = match quote_accounts {
+                    Some((_, _, _, quote_mint, _)) => {
+                        let referrer_quote_token_account = next_account_info(account_info_iter)?;
+                        assert_token_account(
+                            referrer_quote_token_account,
+                            referrer.key,
+                            quote_mint.key,
+                            EscrowError::InvalidQuoteTokenAccount,
+                        )?;
+                        referrer_quote_token_account
+                    }
+                    None => referrer,
+                };
+                assert_writable(referrer_payout_account)?;
+
+                let referral_fee =
+                    referral_amount(platform_fee, paltform_info.referral_share_bps)?;
+                Self::record_referral(
+                    program_id,
+                    platform_state_account,
+                    referrer,
+                    referrer_state_account,
+                    &lister_info.quote_mint,
+                    taker,
+                    system_program,
+                    referral_fee,
+                )?;
+                Some((referrer_payout_account, referral_fee))
+            }
+            None => None,
+        };
+        let platform_fee = platform_fee
+            .checked_sub(referral.map_or(0, |(_, referral_fee)| referral_fee))
+            .ok_or(EscrowError::MathOverflow)?;
+
+        //* the rest of the platform fee is split between the treasury and the fee
+        //* recipients, the treasury takes the rounding dust
+        let (platform_fee, recipient_fees) = paltform_info.split_platform_fee(platform_fee)?;
+        let fee_recipients = fee_recipient_accounts.into_iter().zip(recipient_fees);
+
+        if let Some((
+            takers_quote_token_account,
+            sellers_quote_token_account,
+            treasury_quote_token_account,
+            quote_mint,
+            quote_decimals,
+        )) = quote_accounts
+        {
+            Self::transfer_tokens(
+                token_program,
+                takers_quote_token_account,
+                quote_mint,
+                sellers_quote_token_account,
+                taker,
+                amount_expected_by_seller,
+                quote_decimals,
+                &[],
+            )?;
+            Self::transfer_tokens(
+                token_program,
+                takers_quote_token_account,
+                quote_mint,
+                treasury_quote_token_account,
+                taker,
+                platform_fee,
+                quote_decimals,
+                &[],
+            )?;
+            for (account, amount) in royalties
+                .into_iter()
+                .chain(fee_recipients)
+                .chain(referral)
+            {
+                Self::transfer_tokens(
+                    token_program,
+                    takers_quote_token_account,
+                    quote_mint,
+                    account,
+                    taker,
+                    amount,
+                    quote_decimals,
+                    &[],
+                )?;
+            }
+        } else {
+            //* transer SOL to initializers_main_account
+            invoke(
+                &transfer(
+                    taker.key,
+                    initializers_main_account.key,
+                    amount_expected_by_seller,
+                ),
+                &[
+                    taker.clone(),
+                    initializers_main_account.clone(),
+                    taker.clone(),
+                    system_program.clone(),
+                ],
+            )?;
+            //* transer platform fees to treasury_account
+            msg!("1");
+
+            invoke(
+                &transfer(taker.key, platform_treasury.key, platform_fee),
+                &[
+                    taker.clone(),
+                    platform_treasury.clone(),
+                    taker.clone(),
+                    system_program.clone(),
+                ],
+            )?;
+            msg!("2");
+
+            for (account, amount) in royalties
+                .into_iter()
+                .chain(fee_recipients)
+                .chain(referral)
+            {
+                invoke(
+                    &transfer(taker.key, account.key, amount),
+                    &[
+                        taker.clone(),
+                        account.clone(),
+                        system_program.clone(),
+                    ],
+                )?;
+            }
+        }
         //* transfer token to the buyer
-        let tranfer_instructions = spl_token::instruction::transfer(
+        let tranfer_instructions = spl_token::instruction::transfer_checked(
             token_program.key,
             pdas_token_account.key,
+            token_mint.key,
             takers_token_account.key,
             pda_account.key,
             &[],
-            expected_token_amount_by_taker * 100,
+            expected_token_amount_by_taker,
+            token_state_info.decimals,
         )?;
         invoke_signed(
             &tranfer_instructions,
             &[
                 pdas_token_account.clone(),
+                token_mint.clone(),
                 takers_token_account.clone(),
                 pda_account.clone(),
+                token_program.clone(),
             ],
             &[&[
                 (initializers_main_account.key).as_ref(),
                 (token_mint.key).as_ref(),
                 (token_state_account.key).as_ref(),
-
+                &listing_id.to_le_bytes(),
                 &[nonce],
             ]],
         )?;
@@ -449,27 +1044,56 @@ impl Processor {
         msg!("3");
         //* update the state of lister_info
 
-        lister_info.token_amount = lister_info.token_amount - expected_token_amount_by_taker;
+        lister_info.token_amount = lister_info
+            .token_amount
+            .checked_sub(expected_token_amount_by_taker)
+            .ok_or(EscrowError::InsufficientListedTokens)?;
+
+        token_state_info.total_no_of_tokens_listed = token_state_info
+            .total_no_of_tokens_listed
+            .checked_sub(expected_token_amount_by_taker)
+            .ok_or(EscrowError::MathOverflow)?;
 
-        token_state_info.total_no_of_tokens_listed = token_state_info.total_no_of_tokens_listed - expected_token_amount_by_taker;
+        TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
 
         if lister_info.token_amount == 0 {
-            lister_info.is_initialized = false;
+            //* fully filled, give the rent of the listing back to the seller
+            Self::close_listing(
+                token_program,
+                pda_account,
+                pdas_token_account,
+                token_mint,
+                sellers_token_account,
+                initializers_main_account,
+                token_state_info.decimals,
+                &[&[
+                    (initializers_main_account.key).as_ref(),
+                    (token_mint.key).as_ref(),
+                    (token_state_account.key).as_ref(),
+                    &listing_id.to_le_bytes(),
+                    &[nonce],
+                ]],
+            )?;
+        } else {
+            ListerState::pack(lister_info, &mut pda_account.try_borrow_mut_data()?)?;
         }
 
-        ListerState::pack(lister_info, &mut pda_account.try_borrow_mut_data()?)?;
-        TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
-
         msg!("4");
 
         Ok(())
     }
 
-    pub fn process_cancel(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    pub fn process_cancel(
+        accounts: &[AccountInfo],
+        listing_id: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
 
         let user = next_account_info(account_info_iter)?;
 
+        assert_signer(user, EscrowError::SellerNotSigner)?;
+
         let token_mint = next_account_info(account_info_iter)?;
 
         let user_token_account = next_account_info(account_info_iter)?;
@@ -481,57 +1105,2843 @@ impl Processor {
         let token_state_account = next_account_info(account_info_iter)?;
 
 
-        let (pda, nonce) =
-            Pubkey::find_program_address(&[user.key.as_ref(), token_mint.key.as_ref(),token_state_account.key.as_ref()], program_id);
+        let (pda, nonce) = ListerState::find_address(
+            program_id,
+            user.key,
+            token_mint.key,
+            token_state_account.key,
+            listing_id,
+        );
 
         if pda != *pda_account.key {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(EscrowError::InvalidListingPda.into());
         }
 
         //* check owner of escrow account is the program
-        if pda_account.owner != program_id {
-            return Err(ProgramError::IncorrectProgramId);
-        }
+        assert_owned_by(pda_account, program_id, EscrowError::ListingNotOwnedByProgram)?;
 
         //* unpack the escrow state for some validation checks
-        let mut lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+        let lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+        //* english auctions are only sold through SettleAuction
+        if lister_info.english_auction {
+            return Err(EscrowError::EnglishAuctionListing.into());
+        }
 
         //* check if the user cancelling the listing is actually
         //* the user who have listed it
         if lister_info.seller_pubkey != *user.key {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(EscrowError::SellerMismatch.into());
         }
         if lister_info.token_account_pubkey != *pdas_token_account.key {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(EscrowError::EscrowTokenAccountMismatch.into());
         }
+        assert_token_account(
+            pdas_token_account,
+            pda_account.key,
+            token_mint.key,
+            EscrowError::InvalidEscrowTokenAccount,
+        )?;
 
         let token_program = next_account_info(account_info_iter)?;
 
-        //* transfer the token amount back to the initializer
+        assert_token_program(token_program)?;
+        assert_writable(user)?;
+        assert_writable(user_token_account)?;
+        assert_writable(pdas_token_account)?;
+        assert_writable(pda_account)?;
+        assert_writable(token_state_account)?;
+
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let mut token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+
+        //* transfer the whole escrow back to the initializer so it can be closed
 
-        let tranfer_instructions = spl_token::instruction::transfer(
+        let tranfer_instructions = spl_token::instruction::transfer_checked(
             token_program.key,
             pdas_token_account.key,
+            token_mint.key,
             user_token_account.key,
             pda_account.key,
             &[],
-            lister_info.token_amount * 100,
+            Self::escrow_balance(pdas_token_account)?,
+            token_state_info.decimals,
         )?;
         invoke_signed(
             &tranfer_instructions,
             &[
                 pdas_token_account.clone(),
+                token_mint.clone(),
                 user_token_account.clone(),
                 pda_account.clone(),
+                token_program.clone(),
             ],
-            &[&[(user.key).as_ref(),(token_mint.key).as_ref(),token_state_account.key.as_ref(), &[nonce]]],
+            &[&[
+                (user.key).as_ref(),
+                (token_mint.key).as_ref(),
+                token_state_account.key.as_ref(),
+                &listing_id.to_le_bytes(),
+                &[nonce],
+            ]],
         )?;
-        //* set the escorw state is_initialized to false
+        token_state_info.total_no_of_tokens_listed = token_state_info
+            .total_no_of_tokens_listed
+            .checked_sub(lister_info.token_amount)
+            .ok_or(EscrowError::MathOverflow)?;
 
-        lister_info.is_initialized = false;
+        TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
 
-        ListerState::pack(lister_info, &mut pda_account.try_borrow_mut_data()?)?;
+        //* close the escrow token account and the escorw state
+        Self::close_listing(
+            token_program,
+            pda_account,
+            pdas_token_account,
+            token_mint,
+            user_token_account,
+            user,
+            token_state_info.decimals,
+            &[&[
+                (user.key).as_ref(),
+                (token_mint.key).as_ref(),
+                token_state_account.key.as_ref(),
+                &listing_id.to_le_bytes(),
+                &[nonce],
+            ]],
+        )?;
+
+        Ok(())
+    }
+
+    //* Reprice an open listing, or move tokens in and out of its escrow
+    pub fn process_update_listing(
+        accounts: &[AccountInfo],
+        price: Option<u64>,
+        deposit: Option<u64>,
+        withdraw: Option<u64>,
+        unit: AmountUnit,
+        listing_id: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let seller = next_account_info(account_info_iter)?;
+
+        assert_signer(seller, EscrowError::SellerNotSigner)?;
 
+        let seller_token_account = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let pda_account = next_account_info(account_info_iter)?;
+
+        let pdas_token_account = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+        assert_token_program(token_program)?;
+        assert_writable(seller)?;
+        assert_writable(seller_token_account)?;
+        assert_writable(pda_account)?;
+        assert_writable(pdas_token_account)?;
+        assert_writable(token_state_account)?;
+
+        let (pda, nonce) = ListerState::find_address(
+            program_id,
+            seller.key,
+            token_mint.key,
+            token_state_account.key,
+            listing_id,
+        );
+
+        if pda != *pda_account.key {
+            return Err(EscrowError::InvalidListingPda.into());
+        }
+        assert_owned_by(pda_account, program_id, EscrowError::ListingNotOwnedByProgram)?;
+
+        let mut lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+        //* english auctions are only sold through SettleAuction
+        if lister_info.english_auction {
+            return Err(EscrowError::EnglishAuctionListing.into());
+        }
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let mut token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+
+        if lister_info.seller_pubkey != *seller.key {
+            return Err(EscrowError::SellerMismatch.into());
+        }
+        if lister_info.token_account_pubkey != *pdas_token_account.key {
+            return Err(EscrowError::EscrowTokenAccountMismatch.into());
+        }
+        assert_token_account(
+            pdas_token_account,
+            pda_account.key,
+            token_mint.key,
+            EscrowError::InvalidEscrowTokenAccount,
+        )?;
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+
+        if let Some(price) = price {
+            //* the price of an auction follows its schedule
+            if lister_info.is_dutch_auction() {
+                return Err(EscrowError::InvalidAuction.into());
+            }
+            msg!(
+                "expected_amount_per_token updated : {} -> {}",
+                lister_info.expected_amount_per_token,
+                price
+            );
+            lister_info.expected_amount_per_token = price;
+        }
+
+        //* top up the escrow from the seller
+        if let Some(deposit) = deposit {
+            let deposit = unit.to_base_units(deposit, token_state_info.decimals)?;
+
+            Self::transfer_tokens(
+                token_program,
+                seller_token_account,
+                token_mint,
+                pdas_token_account,
+                seller,
+                deposit,
+                token_state_info.decimals,
+                &[],
+            )?;
+
+            lister_info.token_amount = lister_info
+                .token_amount
+                .checked_add(deposit)
+                .ok_or(EscrowError::MathOverflow)?;
+            token_state_info.total_no_of_tokens_listed = token_state_info
+                .total_no_of_tokens_listed
+                .checked_add(deposit)
+                .ok_or(EscrowError::MathOverflow)?;
+        }
+
+        //* hand part of the remaining tokens back to the seller
+        if let Some(withdraw) = withdraw {
+            let withdraw = unit.to_base_units(withdraw, token_state_info.decimals)?;
+
+            lister_info.token_amount = lister_info
+                .token_amount
+                .checked_sub(withdraw)
+                .ok_or(EscrowError::InsufficientListedTokens)?;
+            token_state_info.total_no_of_tokens_listed = token_state_info
+                .total_no_of_tokens_listed
+                .checked_sub(withdraw)
+                .ok_or(EscrowError::MathOverflow)?;
+
+            Self::transfer_tokens(
+                token_program,
+                pdas_token_account,
+                token_mint,
+                seller_token_account,
+                pda_account,
+                withdraw,
+                token_state_info.decimals,
+                &[&[
+                    seller.key.as_ref(),
+                    token_mint.key.as_ref(),
+                    token_state_account.key.as_ref(),
+                    &listing_id.to_le_bytes(),
+                    &[nonce],
+                ]],
+            )?;
+        }
+
+        TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
+
+        msg!("lister info {:?}", lister_info);
+
+        if lister_info.token_amount == 0 {
+            //* everything was withdrawn, close the listing
+            Self::close_listing(
+                token_program,
+                pda_account,
+                pdas_token_account,
+                token_mint,
+                seller_token_account,
+                seller,
+                token_state_info.decimals,
+                &[&[
+                    seller.key.as_ref(),
+                    token_mint.key.as_ref(),
+                    token_state_account.key.as_ref(),
+                    &listing_id.to_le_bytes(),
+                    &[nonce],
+                ]],
+            )?;
+        } else {
+            ListerState::pack(lister_info, &mut pda_account.try_borrow_mut_data()?)?;
+        }
+
+        Ok(())
+    }
+
+    //* Permissionless, hand the tokens of an expired listing back to the seller
+    pub fn process_close_expired_listing(
+        accounts: &[AccountInfo],
+        listing_id: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let seller = next_account_info(account_info_iter)?; // seller of the listing, not a signer
+
+        let seller_token_account = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let pda_account = next_account_info(account_info_iter)?;
+
+        let pdas_token_account = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+        assert_token_program(token_program)?;
+        assert_writable(seller)?;
+        assert_writable(seller_token_account)?;
+        assert_writable(pda_account)?;
+        assert_writable(pdas_token_account)?;
+        assert_writable(token_state_account)?;
+
+        let (pda, nonce) = ListerState::find_address(
+            program_id,
+            seller.key,
+            token_mint.key,
+            token_state_account.key,
+            listing_id,
+        );
+
+        if pda != *pda_account.key {
+            return Err(EscrowError::InvalidListingPda.into());
+        }
+        assert_owned_by(pda_account, program_id, EscrowError::ListingNotOwnedByProgram)?;
+
+        let lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let mut token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+
+        if !lister_info.is_expired(Clock::get()?.unix_timestamp) {
+            return Err(EscrowError::ListingNotExpired.into());
+        }
+        if lister_info.seller_pubkey != *seller.key {
+            return Err(EscrowError::SellerMismatch.into());
+        }
+        if lister_info.token_account_pubkey != *pdas_token_account.key {
+            return Err(EscrowError::EscrowTokenAccountMismatch.into());
+        }
+        assert_token_account(
+            pdas_token_account,
+            pda_account.key,
+            token_mint.key,
+            EscrowError::InvalidEscrowTokenAccount,
+        )?;
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+
+        //* anyone can call this, so the tokens may only go to the seller
+        assert_token_account(
+            seller_token_account,
+            seller.key,
+            token_mint.key,
+            EscrowError::InvalidSellerTokenAccount,
+        )?;
+
+        Self::transfer_tokens(
+            token_program,
+            pdas_token_account,
+            token_mint,
+            seller_token_account,
+            pda_account,
+            Self::escrow_balance(pdas_token_account)?,
+            token_state_info.decimals,
+            &[&[
+                seller.key.as_ref(),
+                token_mint.key.as_ref(),
+                token_state_account.key.as_ref(),
+                &listing_id.to_le_bytes(),
+                &[nonce],
+            ]],
+        )?;
+
+        token_state_info.total_no_of_tokens_listed = token_state_info
+            .total_no_of_tokens_listed
+            .checked_sub(lister_info.token_amount)
+            .ok_or(EscrowError::MathOverflow)?;
+
+        TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
+
+        Self::close_listing(
+            token_program,
+            pda_account,
+            pdas_token_account,
+            token_mint,
+            seller_token_account,
+            seller,
+            token_state_info.decimals,
+            &[&[
+                seller.key.as_ref(),
+                token_mint.key.as_ref(),
+                token_state_account.key.as_ref(),
+                &listing_id.to_le_bytes(),
+                &[nonce],
+            ]],
+        )?;
+
+        Ok(())
+    }
+
+    //* Propose a new admin for the platform, the proposal only takes
+    //* effect once the proposed account signs an AcceptAdmin
+    pub fn process_propose_admin(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let admin = next_account_info(account_info_iter)?; // current admin
+
+        let platfrom_account = next_account_info(account_info_iter)?; // platform state account
+
+        assert_writable(platfrom_account)?;
+
+        let new_admin = next_account_info(account_info_iter)?; // proposed admin
+
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
+
+        let mut platform_info = PlatformState::unpack(&platfrom_account.try_borrow_data()?)?;
+        Self::assert_admin(admin, &platform_info)?;
+
+        //* proposing the default pubkey withdraws a pending proposal
+        platform_info.pending_admin = *new_admin.key;
+
+        PlatformState::pack(platform_info, &mut platfrom_account.try_borrow_mut_data()?)?;
+
+        msg!("pending admin : {}", platform_info.pending_admin);
+
+        Ok(())
+    }
+
+    //* The pending admin accepts the platform authority
+    pub fn process_accept_admin(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let new_admin = next_account_info(account_info_iter)?; // pending admin
+
+        let platfrom_account = next_account_info(account_info_iter)?; // platform state account
+
+        assert_writable(platfrom_account)?;
+
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
+
+        let mut platform_info = PlatformState::unpack(&platfrom_account.try_borrow_data()?)?;
+
+        if platform_info.pending_admin == Pubkey::default()
+            || platform_info.pending_admin != *new_admin.key
+        {
+            return Err(EscrowError::InvalidPendingAdmin.into());
+        }
+        assert_signer(new_admin, EscrowError::AdminNotSigner)?;
+
+        platform_info.admin = *new_admin.key;
+        platform_info.pending_admin = Pubkey::default();
+
+        PlatformState::pack(platform_info, &mut platfrom_account.try_borrow_mut_data()?)?;
+
+        msg!("new admin : {}", platform_info.admin);
+
+        Ok(())
+    }
+
+    //* Update the platform config in place, admin only
+    pub fn process_update_platform_config(
+        accounts: &[AccountInfo],
+        config: PlatformConfigUpdate,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let admin = next_account_info(account_info_iter)?; // admin account key
+
+        let platfrom_account = next_account_info(account_info_iter)?; // platform state account
+
+        assert_writable(platfrom_account)?;
+
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
+
+        let mut platform_info = PlatformState::unpack(&platfrom_account.try_borrow_data()?)?;
+        Self::assert_admin(admin, &platform_info)?;
+
+        //* log every changed field so off-chain systems can track the config
+        if let Some(platform_fee_bps) = config.platform_fee_bps {
+            assert_valid_platform_fee(platform_fee_bps)?;
+            msg!(
+                "platform_fee_bps updated : {} -> {}",
+                platform_info.platform_fee_bps,
+                platform_fee_bps
+            );
+            platform_info.platform_fee_bps = platform_fee_bps;
+        }
+        if let Some(treasury_account) = config.treasury_account {
+            msg!(
+                "treasury_account updated : {} -> {}",
+                platform_info.treasury_account,
+                treasury_account
+            );
+            platform_info.treasury_account = treasury_account;
+        }
+        if let Some(quote_mints) = config.quote_mints {
+            msg!(
+                "quote_mints updated : {:?} -> {:?}",
+                platform_info.quote_mints,
+                quote_mints
+            );
+            platform_info.quote_mints = quote_mints;
+        }
+        if let Some(referral_share_bps) = config.referral_share_bps {
+            assert_valid_referral_share(referral_share_bps)?;
+            msg!(
+                "referral_share_bps updated : {} -> {}",
+                platform_info.referral_share_bps,
+                referral_share_bps
+            );
+            platform_info.referral_share_bps = referral_share_bps;
+        }
+        if let Some(fee_recipients) = config.fee_recipients {
+            msg!(
+                "fee_recipients updated : {:?} -> {:?}",
+                platform_info.fee_recipients,
+                fee_recipients
+            );
+            platform_info.fee_recipients = fee_recipients;
+        }
+        //* checked once all fields are applied, a new treasury has to be part of the split
+        if !platform_info.is_valid_fee_split() {
+            return Err(EscrowError::InvalidFeeSplit.into());
+        }
+
+        PlatformState::pack(platform_info, &mut platfrom_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    //* Turn creator royalties on or off for a mint, admin only
+    pub fn process_set_royalty_enforcement(
+        accounts: &[AccountInfo],
+        enforce_royalties: bool,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let admin = next_account_info(account_info_iter)?; // admin account key
+
+        let platfrom_account = next_account_info(account_info_iter)?; // platform state account
+
+        let token_state_account = next_account_info(account_info_iter)?; // token state account
+
+        assert_writable(token_state_account)?;
+
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+
+        let platform_info = PlatformState::unpack(&platfrom_account.try_borrow_data()?)?;
+        Self::assert_admin(admin, &platform_info)?;
+
+        let mut token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+
+        //* the token state has to belong to the platform of the admin
+        let (token_pda, _nonce) = TokenState::find_address(
+            program_id,
+            platfrom_account.key,
+            &token_state_info.token_mint,
+        );
+        if token_pda != *token_state_account.key {
+            return Err(EscrowError::InvalidTokenStatePda.into());
+        }
+
+        msg!(
+            "enforce_royalties updated : {} -> {}",
+            token_state_info.enforce_royalties,
+            enforce_royalties
+        );
+        token_state_info.enforce_royalties = enforce_royalties;
+
+        TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    //* Allow NFT listings of a collection, admin only
+    pub fn process_add_collection(
+        accounts: &[AccountInfo],
+        collection_mint: Pubkey,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let admin = next_account_info(account_info_iter)?; // admin account key
+
+        let platfrom_account = next_account_info(account_info_iter)?; // platform state account
+
+        let collection_state_account = next_account_info(account_info_iter)?; // collection state
+
+        let system_program = next_account_info(account_info_iter)?; // system_program account
+
+        assert_writable(collection_state_account)?;
+        assert_system_program(system_program)?;
+
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
+        let platform_info = PlatformState::unpack(&platfrom_account.try_borrow_data()?)?;
+        Self::assert_admin(admin, &platform_info)?;
+
+        let (collection_pda, nonce) =
+            CollectionState::find_address(program_id, platfrom_account.key, &collection_mint);
+        if collection_pda != *collection_state_account.key {
+            return Err(EscrowError::InvalidCollectionStatePda.into());
+        }
+
+        invoke_signed(
+            &create_account(
+                admin.key,
+                collection_state_account.key,
+                Rent::default().minimum_balance(CollectionState::LEN),
+                CollectionState::LEN as u64,
+                program_id,
+            ),
+            &[
+                admin.clone(),
+                collection_state_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                b"collection",
+                platfrom_account.key.as_ref(),
+                collection_mint.as_ref(),
+                &[nonce],
+            ]],
+        )?;
+
+        let collection_info = CollectionState {
+            is_initialized: true,
+            collection_mint,
+        };
+        CollectionState::pack(
+            collection_info,
+            &mut collection_state_account.try_borrow_mut_data()?,
+        )?;
+
+        msg!("collection added : {}", collection_mint);
+
+        Ok(())
+    }
+
+    //* Stop allowing new NFT listings of a collection, admin only.
+    //* Open listings of the collection stay fillable.
+    pub fn process_remove_collection(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let admin = next_account_info(account_info_iter)?; // admin account key
+
+        let platfrom_account = next_account_info(account_info_iter)?; // platform state account
+
+        let collection_state_account = next_account_info(account_info_iter)?; // collection state
+
+        assert_writable(admin)?;
+        assert_writable(collection_state_account)?;
+
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
+        let platform_info = PlatformState::unpack(&platfrom_account.try_borrow_data()?)?;
+        Self::assert_admin(admin, &platform_info)?;
+
+        assert_owned_by(
+            collection_state_account,
+            program_id,
+            EscrowError::CollectionNotAllowed,
+        )?;
+        let collection_info =
+            CollectionState::unpack(&collection_state_account.try_borrow_data()?)?;
+        let (collection_pda, _nonce) = CollectionState::find_address(
+            program_id,
+            platfrom_account.key,
+            &collection_info.collection_mint,
+        );
+        if collection_pda != *collection_state_account.key {
+            return Err(EscrowError::InvalidCollectionStatePda.into());
+        }
+
+        //* drain the collection state, the rent goes back to the admin
+        let collection_lamports = collection_state_account.lamports();
+        **admin.try_borrow_mut_lamports()? = admin
+            .lamports()
+            .checked_add(collection_lamports)
+            .ok_or(EscrowError::MathOverflow)?;
+        **collection_state_account.try_borrow_mut_lamports()? = 0;
+        collection_state_account.try_borrow_mut_data()?.fill(0);
+
+        msg!("collection removed : {}", collection_info.collection_mint);
+
+        Ok(())
+    }
+
+    //* Buyer side intent, the lamports for the whole offer are escrowed in the offer PDA
+    pub fn process_make_offer(
+        accounts: &[AccountInfo],
+        token_amount: u64,
+        price_per_token: u64,
+        unit: AmountUnit,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let buyer = next_account_info(account_info_iter)?;
+
+        assert_signer(buyer, EscrowError::TakerNotSigner)?;
+
+        let offer_account = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let system_program = next_account_info(account_info_iter)?;
+
+        assert_writable(buyer)?;
+        assert_writable(offer_account)?;
+        assert_system_program(system_program)?;
+
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+
+        let token_amount = unit.to_base_units(token_amount, token_state_info.decimals)?;
+        if token_amount == 0 || (token_state_info.nft_mode && token_amount != 1) {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
+        let (offer_pda, nonce) = OfferState::find_address(
+            program_id,
+            buyer.key,
+            token_mint.key,
+            token_state_account.key,
+        );
+        if offer_pda != *offer_account.key {
+            return Err(EscrowError::InvalidOfferPda.into());
+        }
+
+        //* Create a new account for the offer
+        if offer_account.owner != program_id {
+            invoke_signed(
+                &create_account(
+                    buyer.key,
+                    offer_account.key,
+                    Rent::default().minimum_balance(OfferState::LEN),
+                    OfferState::LEN as u64,
+                    program_id,
+                ),
+                &[
+                    buyer.clone(),
+                    offer_account.clone(),
+                    system_program.clone(),
+                ],
+                &[&[
+                    b"offer",
+                    buyer.key.as_ref(),
+                    token_mint.key.as_ref(),
+                    token_state_account.key.as_ref(),
+                    &[nonce],
+                ]],
+            )?;
+        }
+
+        let mut offer_info = OfferState::unpack_unchecked(&offer_account.try_borrow_data()?)?;
+        if offer_info.is_initialized {
+            return Err(EscrowError::OfferAlreadyActive.into());
+        }
+
+        //* escrow the total price of the offer on top of the rent
+        let escrow_amount = total_price(token_amount, price_per_token, token_state_info.decimals)?;
+        invoke(
+            &transfer(buyer.key, offer_account.key, escrow_amount),
+            &[
+                buyer.clone(),
+                offer_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+
+        offer_info.is_initialized = true;
+        offer_info.buyer_pubkey = *buyer.key;
+        offer_info.token_mint = *token_mint.key;
+        offer_info.token_amount = token_amount;
+        offer_info.price_per_token = price_per_token;
+
+        OfferState::pack(offer_info, &mut offer_account.try_borrow_mut_data()?)?;
+        msg!("offer info {:?}", offer_info);
+
+        Ok(())
+    }
+
+    //* The buyer closes the offer and takes back the escrowed lamports and the rent
+    pub fn process_cancel_offer(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let buyer = next_account_info(account_info_iter)?;
+
+        assert_signer(buyer, EscrowError::TakerNotSigner)?;
+
+        let offer_account = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        assert_writable(buyer)?;
+        assert_writable(offer_account)?;
+
+        let (offer_pda, _nonce) = OfferState::find_address(
+            program_id,
+            buyer.key,
+            token_mint.key,
+            token_state_account.key,
+        );
+        if offer_pda != *offer_account.key {
+            return Err(EscrowError::InvalidOfferPda.into());
+        }
+        assert_owned_by(offer_account, program_id, EscrowError::OfferNotOwnedByProgram)?;
+
+        let offer_info = OfferState::unpack(&offer_account.try_borrow_data()?)?;
+        if offer_info.buyer_pubkey != *buyer.key {
+            return Err(EscrowError::BuyerMismatch.into());
+        }
+
+        Self::close_offer(offer_account, buyer)
+    }
+
+    //* A holder of the mint sells into an offer, fully or partially
+    pub fn process_accept_offer(
+        accounts: &[AccountInfo],
+        amount: u64,
+        unit: AmountUnit,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let seller = next_account_info(account_info_iter)?;
+
+        assert_signer(seller, EscrowError::SellerNotSigner)?;
+
+        let seller_token_account = next_account_info(account_info_iter)?;
+
+        let buyer = next_account_info(account_info_iter)?; // buyer of the offer, gets the rent back
+
+        let buyer_token_account = next_account_info(account_info_iter)?;
+
+        let offer_account = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let platform_state_account = next_account_info(account_info_iter)?;
+
+        let platform_treasury = next_account_info(account_info_iter)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+        assert_token_program(token_program)?;
+        assert_writable(seller)?;
+        assert_writable(seller_token_account)?;
+        assert_writable(buyer)?;
+        assert_writable(buyer_token_account)?;
+        assert_writable(offer_account)?;
+        assert_writable(platform_treasury)?;
+
+        assert_owned_by(
+            platform_state_account,
+            program_id,
+            EscrowError::PlatformStateNotOwnedByProgram,
+        )?;
+        let platform_info = PlatformState::unpack(&platform_state_account.try_borrow_data()?)?;
+        if platform_info.treasury_account != *platform_treasury.key {
+            return Err(EscrowError::InvalidTreasuryAccount.into());
+        }
+
+        //* the token state has to belong to the platform the fees are read from
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let (token_pda, _nonce) =
+            TokenState::find_address(program_id, platform_state_account.key, token_mint.key);
+        if token_pda != *token_state_account.key {
+            return Err(EscrowError::InvalidTokenStatePda.into());
+        }
+        let token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+
+        let (offer_pda, _nonce) = OfferState::find_address(
+            program_id,
+            buyer.key,
+            token_mint.key,
+            token_state_account.key,
+        );
+        if offer_pda != *offer_account.key {
+            return Err(EscrowError::InvalidOfferPda.into());
+        }
+        assert_owned_by(offer_account, program_id, EscrowError::OfferNotOwnedByProgram)?;
+        let mut offer_info = OfferState::unpack(&offer_account.try_borrow_data()?)?;
+        if offer_info.buyer_pubkey != *buyer.key {
+            return Err(EscrowError::BuyerMismatch.into());
+        }
+        assert_token_account(
+            buyer_token_account,
+            buyer.key,
+            token_mint.key,
+            EscrowError::InvalidBuyerTokenAccount,
+        )?;
+
+        let amount = unit.to_base_units(amount, token_state_info.decimals)?;
+        if amount == 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+        let remaining = offer_info
+            .token_amount
+            .checked_sub(amount)
+            .ok_or(EscrowError::InsufficientOfferedTokens)?;
+        if token_state_info.nft_mode && remaining != 0 {
+            return Err(EscrowError::PartialFillNotAllowed.into());
+        }
+
+        //* partial fills of an offer add up to exactly the escrowed amount
+        let total = Self::escrow_released(
+            offer_info.token_amount,
+            amount,
+            offer_info.price_per_token,
+            token_state_info.decimals,
+        )?;
+        msg!("total {}", total);
+
+        //* fee is rounded up, the seller gets the remainder
+        let (platform_fee, amount_expected_by_seller) =
+            split_fee(total, platform_info.platform_fee_bps)?;
+        msg!("platform_fee {}", platform_fee);
+
+        //* the platform fee is split between the treasury and the fee recipients,
+        //* the treasury takes the rounding dust
+        let fee_recipient_accounts =
+            Self::fee_recipient_accounts(account_info_iter, &platform_info, None)?;
+        let (treasury_fee, recipient_fees) = platform_info.split_platform_fee(platform_fee)?;
+
+        //* creator royalties are paid out of the seller's share
+        let royalties = if token_state_info.enforce_royalties {
+            Self::creator_royalties(account_info_iter, token_mint.key, None, total)?
+        } else {
+            vec![]
+        };
+        let amount_expected_by_seller =
+            Self::seller_proceeds(amount_expected_by_seller, &royalties)?;
+        msg!("amount_expected_by_seller {}", amount_expected_by_seller);
+
+        //* transfer the tokens to the buyer
+        Self::transfer_tokens(
+            token_program,
+            seller_token_account,
+            token_mint,
+            buyer_token_account,
+            seller,
+            amount,
+            token_state_info.decimals,
+            &[],
+        )?;
+
+        //* pay out of the lamports escrowed in the offer
+        Self::transfer_lamports(offer_account, seller, amount_expected_by_seller)?;
+        Self::transfer_lamports(offer_account, platform_treasury, treasury_fee)?;
+        for (account, amount) in fee_recipient_accounts
+            .into_iter()
+            .zip(recipient_fees)
+            .chain(royalties)
+        {
+            Self::transfer_lamports(offer_account, account, amount)?;
+        }
+
+        offer_info.token_amount = remaining;
+        if remaining == 0 {
+            Self::close_offer(offer_account, buyer)?;
+        } else {
+            OfferState::pack(offer_info, &mut offer_account.try_borrow_mut_data()?)?;
+        }
+
+        Ok(())
+    }
+
+    //* Open the order book of a token state, admin only
+    pub fn process_create_order_book(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let admin = next_account_info(account_info_iter)?; // admin account key
+
+        let platfrom_account = next_account_info(account_info_iter)?; // platform state account
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let order_book_account = next_account_info(account_info_iter)?;
+
+        let vault_token_account = next_account_info(account_info_iter)?; // ATA of the order book
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+        let system_program = next_account_info(account_info_iter)?;
+
+        let associated_token_program = next_account_info(account_info_iter)?;
+
+        let rent_sysvar = next_account_info(account_info_iter)?;
+
+        assert_writable(order_book_account)?;
+        assert_writable(vault_token_account)?;
+        assert_token_program(token_program)?;
+        assert_system_program(system_program)?;
+        assert_associated_token_program(associated_token_program)?;
+        assert_rent_sysvar(rent_sysvar)?;
+
+        assert_owned_by(platfrom_account, program_id, EscrowError::PlatformStateNotOwnedByProgram)?;
+        let platform_info = PlatformState::unpack(&platfrom_account.try_borrow_data()?)?;
+        Self::assert_admin(admin, &platform_info)?;
+
+        Self::assert_token_state(program_id, platfrom_account, token_mint, token_state_account)?;
+
+        let (order_book_pda, nonce) = OrderBook::find_address(program_id, token_state_account.key);
+        if order_book_pda != *order_book_account.key {
+            return Err(EscrowError::InvalidOrderBookPda.into());
+        }
+
+        invoke_signed(
+            &create_account(
+                admin.key,
+                order_book_account.key,
+                Rent::default().minimum_balance(OrderBook::LEN),
+                OrderBook::LEN as u64,
+                program_id,
+            ),
+            &[
+                admin.clone(),
+                order_book_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[b"order_book", token_state_account.key.as_ref(), &[nonce]]],
+        )?;
+
+        //* the vault is the associated token account of the order book PDA
+        if *vault_token_account.key
+            != get_associated_token_address(order_book_account.key, token_mint.key)
+        {
+            return Err(EscrowError::InvalidEscrowTokenAccount.into());
+        }
+        invoke(
+            &create_associated_token_account(admin.key, order_book_account.key, token_mint.key),
+            &[
+                admin.clone(),
+                vault_token_account.clone(),
+                order_book_account.clone(),
+                token_mint.clone(),
+                system_program.clone(),
+                token_program.clone(),
+                rent_sysvar.clone(),
+                associated_token_program.clone(),
+            ],
+        )?;
+
+        let order_book = OrderBook {
+            is_initialized: true,
+            token_state: *token_state_account.key,
+            next_order_id: 0,
+            bids: vec![],
+            asks: vec![],
+        };
+        OrderBook::pack(order_book, &mut order_book_account.try_borrow_mut_data()?)?;
+
+        msg!("order book opened : {}", order_book_account.key);
+
+        Ok(())
+    }
+
+    //* Place a limit order, it is matched against the crossing resting orders
+    //* best first at their price and the rest is escrowed in the book
+    pub fn process_place_order(
+        accounts: &[AccountInfo],
+        side: Side,
+        price_per_token: u64,
+        amount: u64,
+        unit: AmountUnit,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let trader = next_account_info(account_info_iter)?;
+
+        assert_signer(trader, EscrowError::TakerNotSigner)?;
+
+        let trader_token_account = next_account_info(account_info_iter)?;
+
+        let order_book_account = next_account_info(account_info_iter)?;
+
+        let vault_token_account = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let platform_state_account = next_account_info(account_info_iter)?;
+
+        let platform_treasury = next_account_info(account_info_iter)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+        let system_program = next_account_info(account_info_iter)?;
+
+        let associated_token_program = next_account_info(account_info_iter)?;
+
+        let rent_sysvar = next_account_info(account_info_iter)?;
+
+        assert_token_program(token_program)?;
+        assert_system_program(system_program)?;
+        assert_associated_token_program(associated_token_program)?;
+        assert_rent_sysvar(rent_sysvar)?;
+        assert_writable(trader)?;
+        assert_writable(trader_token_account)?;
+        assert_writable(order_book_account)?;
+        assert_writable(vault_token_account)?;
+        assert_writable(platform_treasury)?;
+
+        assert_owned_by(
+            platform_state_account,
+            program_id,
+            EscrowError::PlatformStateNotOwnedByProgram,
+        )?;
+        let platform_info = PlatformState::unpack(&platform_state_account.try_borrow_data()?)?;
+        if platform_info.treasury_account != *platform_treasury.key {
+            return Err(EscrowError::InvalidTreasuryAccount.into());
+        }
+
+        let token_state_info = Self::assert_token_state(
+            program_id,
+            platform_state_account,
+            token_mint,
+            token_state_account,
+        )?;
+        let (mut order_book, nonce) = Self::unpack_order_book(
+            program_id,
+            token_state_account,
+            order_book_account,
+            vault_token_account,
+            token_mint,
+        )?;
+        assert_token_account(
+            trader_token_account,
+            trader.key,
+            token_mint.key,
+            EscrowError::InvalidTakerTokenAccount,
+        )?;
+
+        let amount = unit.to_base_units(amount, token_state_info.decimals)?;
+        if amount == 0 || price_per_token == 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+        //* dust orders would let anyone cheaply fill up a side of the book
+        if total_price(amount, price_per_token, token_state_info.decimals)? < MIN_ORDER_LAMPORTS {
+            return Err(EscrowError::OrderTooSmall.into());
+        }
+
+        //* the secondary fee recipients of the platform come before the counterparties
+        let fee_recipient_accounts =
+            Self::fee_recipient_accounts(account_info_iter, &platform_info, None)?;
+
+        let book_seeds: &[&[u8]] = &[b"order_book", token_state_account.key.as_ref(), &[nonce]];
+        let mut remaining = amount;
+
+        //* match against the best resting orders of the other side while they cross,
+        //* every fill is settled at the resting order's price
+        while remaining > 0 {
+            let resting = match order_book.best(side.opposite()) {
+                Some(resting) if OrderBook::crosses(side, price_per_token, resting) => *resting,
+                _ => break,
+            };
+            let fill = remaining.min(resting.quantity);
+            let counterparty = next_account_info(account_info_iter)?;
+            if *counterparty.key != resting.owner {
+                return Err(EscrowError::CounterpartyMismatch.into());
+            }
+            let counterparty_token_account = next_account_info(account_info_iter)?;
+
+            match side {
+                Side::Bid => {
+                    //* the trader pays the ask owner, the tokens come out of the vault
+                    let total =
+                        total_price(fill, resting.price_per_token, token_state_info.decimals)?;
+                    let (platform_fee, amount_expected_by_seller) =
+                        split_fee(total, platform_info.platform_fee_bps)?;
+                    let (treasury_fee, recipient_fees) =
+                        platform_info.split_platform_fee(platform_fee)?;
+
+                    let payments = [
+                        (counterparty, amount_expected_by_seller),
+                        (platform_treasury, treasury_fee),
+                    ];
+                    let recipient_payments =
+                        fee_recipient_accounts.iter().copied().zip(recipient_fees);
+                    for (account, amount) in payments.into_iter().chain(recipient_payments) {
+                        invoke(
+                            &transfer(trader.key, account.key, amount),
+                            &[trader.clone(), account.clone(), system_program.clone()],
+                        )?;
+                    }
+                    Self::transfer_tokens(
+                        token_program,
+                        vault_token_account,
+                        token_mint,
+                        trader_token_account,
+                        order_book_account,
+                        fill,
+                        token_state_info.decimals,
+                        &[book_seeds],
+                    )?;
+                }
+                Side::Ask => {
+                    //* the trader sends the tokens to the associated token account of
+                    //* the bid owner and is paid out of the lamports the bid escrowed
+                    //* in the book
+                    Self::owner_token_account(
+                        trader,
+                        counterparty,
+                        counterparty_token_account,
+                        token_mint,
+                        system_program,
+                        token_program,
+                        rent_sysvar,
+                        associated_token_program,
+                    )?;
+                    let total = Self::escrow_released(
+                        resting.quantity,
+                        fill,
+                        resting.price_per_token,
+                        token_state_info.decimals,
+                    )?;
+                    let (platform_fee, amount_expected_by_seller) =
+                        split_fee(total, platform_info.platform_fee_bps)?;
+                    let (treasury_fee, recipient_fees) =
+                        platform_info.split_platform_fee(platform_fee)?;
+
+                    Self::transfer_tokens(
+                        token_program,
+                        trader_token_account,
+                        token_mint,
+                        counterparty_token_account,
+                        trader,
+                        fill,
+                        token_state_info.decimals,
+                        &[],
+                    )?;
+                    Self::transfer_lamports(order_book_account, trader, amount_expected_by_seller)?;
+                    Self::transfer_lamports(order_book_account, platform_treasury, treasury_fee)?;
+                    for (account, amount) in
+                        fee_recipient_accounts.iter().copied().zip(recipient_fees)
+                    {
+                        Self::transfer_lamports(order_book_account, account, amount)?;
+                    }
+                }
+            }
+
+            msg!(
+                "filled {} at {} against order {}",
+                fill,
+                resting.price_per_token,
+                resting.order_id
+            );
+            //* a resting order filled down to dust leaves the book with a refund
+            if let Some(dust) =
+                order_book.fill_best(side.opposite(), fill, token_state_info.decimals)?
+            {
+                Self::refund_order(
+                    side.opposite(),
+                    &dust,
+                    counterparty,
+                    counterparty_token_account,
+                    trader,
+                    order_book_account,
+                    vault_token_account,
+                    token_mint,
+                    system_program,
+                    token_program,
+                    rent_sysvar,
+                    associated_token_program,
+                    token_state_info.decimals,
+                    book_seeds,
+                )?;
+                msg!("order {} left with dust, refunded {}", dust.order_id, dust.quantity);
+            }
+            remaining -= fill;
+        }
+
+        //* a remainder worth less than the minimum is not rested, the order ends
+        //* immediate-or-cancel
+        if remaining > 0
+            && total_price(remaining, price_per_token, token_state_info.decimals)?
+                < MIN_ORDER_LAMPORTS
+        {
+            msg!("remainder {} below the minimum order, not rested", remaining);
+            remaining = 0;
+        }
+
+        //* escrow whatever is left in the book
+        if remaining > 0 {
+            //* a full side only takes the order in place of a worse priced one
+            if let Some(evicted) = order_book.evict_worst(side, price_per_token)? {
+                let evicted_owner = next_account_info(account_info_iter)?;
+                if *evicted_owner.key != evicted.owner {
+                    return Err(EscrowError::CounterpartyMismatch.into());
+                }
+                //* asks get their tokens back to the owner's associated token account,
+                //* bids their lamports to the owner
+                let evicted_token_account = match side {
+                    Side::Bid => evicted_owner,
+                    Side::Ask => next_account_info(account_info_iter)?,
+                };
+                Self::refund_order(
+                    side,
+                    &evicted,
+                    evicted_owner,
+                    evicted_token_account,
+                    trader,
+                    order_book_account,
+                    vault_token_account,
+                    token_mint,
+                    system_program,
+                    token_program,
+                    rent_sysvar,
+                    associated_token_program,
+                    token_state_info.decimals,
+                    book_seeds,
+                )?;
+                msg!("order evicted : {}", evicted.order_id);
+            }
+
+            match side {
+                Side::Bid => {
+                    let escrow_amount =
+                        total_price(remaining, price_per_token, token_state_info.decimals)?;
+                    invoke(
+                        &transfer(trader.key, order_book_account.key, escrow_amount),
+                        &[
+                            trader.clone(),
+                            order_book_account.clone(),
+                            system_program.clone(),
+                        ],
+                    )?;
+                }
+                Side::Ask => {
+                    Self::transfer_tokens(
+                        token_program,
+                        trader_token_account,
+                        token_mint,
+                        vault_token_account,
+                        trader,
+                        remaining,
+                        token_state_info.decimals,
+                        &[],
+                    )?;
+                }
+            }
+            let order_id = order_book.insert(
+                side,
+                *trader.key,
+                *trader_token_account.key,
+                price_per_token,
+                remaining,
+            )?;
+            msg!("order placed : {} rests {}", order_id, remaining);
+        }
+
+        OrderBook::pack(order_book, &mut order_book_account.try_borrow_mut_data()?)?;
+
+        Ok(())
+    }
+
+    //* Cancel a resting order, its escrow goes back to the owner
+    pub fn process_cancel_order(
+        accounts: &[AccountInfo],
+        side: Side,
+        order_id: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let trader = next_account_info(account_info_iter)?;
+
+        assert_signer(trader, EscrowError::TakerNotSigner)?;
+
+        let trader_token_account = next_account_info(account_info_iter)?;
+
+        let order_book_account = next_account_info(account_info_iter)?;
+
+        let vault_token_account = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+        assert_token_program(token_program)?;
+        assert_writable(trader)?;
+        assert_writable(trader_token_account)?;
+        assert_writable(order_book_account)?;
+        assert_writable(vault_token_account)?;
+
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+        let (mut order_book, nonce) = Self::unpack_order_book(
+            program_id,
+            token_state_account,
+            order_book_account,
+            vault_token_account,
+            token_mint,
+        )?;
+
+        let order = order_book.remove(side, order_id)?;
+        if order.owner != *trader.key {
+            return Err(EscrowError::OrderOwnerMismatch.into());
+        }
+
+        match side {
+            Side::Bid => {
+                let escrow_amount = total_price(
+                    order.quantity,
+                    order.price_per_token,
+                    token_state_info.decimals,
+                )?;
+                Self::transfer_lamports(order_book_account, trader, escrow_amount)?;
+            }
+            Side::Ask => {
+                assert_token_account(
+                    trader_token_account,
+                    trader.key,
+                    token_mint.key,
+                    EscrowError::InvalidSellerTokenAccount,
+                )?;
+                Self::transfer_tokens(
+                    token_program,
+                    vault_token_account,
+                    token_mint,
+                    trader_token_account,
+                    order_book_account,
+                    order.quantity,
+                    token_state_info.decimals,
+                    &[&[b"order_book", token_state_account.key.as_ref(), &[nonce]]],
+                )?;
+            }
+        }
+
+        OrderBook::pack(order_book, &mut order_book_account.try_borrow_mut_data()?)?;
+
+        msg!("order cancelled : {}", order_id);
+
+        Ok(())
+    }
+
+    //* List tokens for an english auction: the listing escrows the tokens like a
+    //* SOL quoted ListToken, the auction PDA passed in front of the listing
+    //* accounts escrows the highest bid
+    pub fn process_list_english_auction(
+        accounts: &[AccountInfo],
+        token_amount: u64,
+        auction: EnglishAuction,
+        unit: AmountUnit,
+        listing_id: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let (auction_account, listing_accounts) = accounts
+            .split_first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        //* bids have to strictly increase, over a window that is still open
+        if auction.min_increment == 0
+            || auction.extension_secs < 0
+            || auction.end_time <= Clock::get()?.unix_timestamp
+        {
+            return Err(EscrowError::InvalidAuction.into());
+        }
+
+        Self::process_init_escrow(
+            listing_accounts,
+            (token_amount, 0),
+            unit,
+            Pubkey::default(),
+            listing_id,
+            0,
+            None,
+            program_id,
+        )?;
+
+        //* the listing accounts have been checked by process_init_escrow
+        let seller = &listing_accounts[0];
+        let pda_account = &listing_accounts[3];
+        let system_program = &listing_accounts[6];
+
+        assert_writable(auction_account)?;
+        let (auction_pda, nonce) = AuctionState::find_address(program_id, pda_account.key);
+        if auction_pda != *auction_account.key {
+            return Err(EscrowError::InvalidAuctionPda.into());
+        }
+
+        //* Create a new account for the auction
+        if auction_account.owner != program_id {
+            invoke_signed(
+                &create_account(
+                    seller.key,
+                    auction_account.key,
+                    Rent::default().minimum_balance(AuctionState::LEN),
+                    AuctionState::LEN as u64,
+                    program_id,
+                ),
+                &[
+                    seller.clone(),
+                    auction_account.clone(),
+                    system_program.clone(),
+                ],
+                &[&[b"auction", pda_account.key.as_ref(), &[nonce]]],
+            )?;
+        }
+
+        let mut auction_info = AuctionState::unpack_unchecked(&auction_account.try_borrow_data()?)?;
+        if auction_info.is_initialized {
+            return Err(EscrowError::ListingAlreadyActive.into());
+        }
+        auction_info.is_initialized = true;
+        auction_info.listing = *pda_account.key;
+        auction_info.reserve_price = auction.reserve_price;
+        auction_info.min_increment = auction.min_increment;
+        auction_info.end_time = auction.end_time;
+        auction_info.extension_secs = auction.extension_secs;
+        AuctionState::pack(auction_info, &mut auction_account.try_borrow_mut_data()?)?;
+
+        let mut lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+        lister_info.english_auction = true;
+        ListerState::pack(lister_info, &mut pda_account.try_borrow_mut_data()?)?;
+        msg!("auction info {:?}", auction_info);
+
+        Ok(())
+    }
+
+    //* List tokens for a token for token swap: the listing escrows the tokens like
+    //* a ListToken quoted in the wanted mint passed in front of the listing
+    //* accounts. Any mint can be wanted, swaps skip the quote mint allowlist.
+    #[allow(clippy::too_many_arguments)]
+    pub fn process_list_swap(
+        accounts: &[AccountInfo],
+        token_amount: u64,
+        rate: u64,
+        unit: AmountUnit,
+        listing_id: u64,
+        expires_at: i64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let (want_mint, listing_accounts) = accounts
+            .split_first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        assert_owned_by(want_mint, &spl_token::id(), EscrowError::InvalidSwapMint)?;
+        Mint::unpack(&want_mint.try_borrow_data()?).map_err(|_| EscrowError::InvalidSwapMint)?;
+
+        Self::process_init_escrow(
+            listing_accounts,
+            (token_amount, rate),
+            unit,
+            Pubkey::default(),
+            listing_id,
+            expires_at,
+            None,
+            program_id,
+        )?;
+
+        //* the listing accounts have been checked by process_init_escrow
+        let token_mint = &listing_accounts[2];
+        let pda_account = &listing_accounts[3];
+        if want_mint.key == token_mint.key {
+            return Err(EscrowError::InvalidSwapMint.into());
+        }
+
+        let mut lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+        lister_info.quote_mint = *want_mint.key;
+        lister_info.swap = true;
+        ListerState::pack(lister_info, &mut pda_account.try_borrow_mut_data()?)?;
+        msg!("swap of {} for {}", token_mint.key, want_mint.key);
+
+        Ok(())
+    }
+
+    //* Bid on an english auction: the bid is escrowed in the auction PDA and the
+    //* previous highest bidder is refunded, late bids extend the auction
+    pub fn process_place_bid(
+        accounts: &[AccountInfo],
+        bid: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let bidder = next_account_info(account_info_iter)?;
+
+        assert_signer(bidder, EscrowError::TakerNotSigner)?;
+
+        let pda_account = next_account_info(account_info_iter)?;
+
+        let auction_account = next_account_info(account_info_iter)?;
+
+        let system_program = next_account_info(account_info_iter)?;
+
+        assert_writable(bidder)?;
+        assert_writable(auction_account)?;
+        assert_system_program(system_program)?;
+
+        let (auction_pda, _nonce) = AuctionState::find_address(program_id, pda_account.key);
+        if auction_pda != *auction_account.key {
+            return Err(EscrowError::InvalidAuctionPda.into());
+        }
+        assert_owned_by(auction_account, program_id, EscrowError::AuctionNotOwnedByProgram)?;
+        let mut auction_info = AuctionState::unpack(&auction_account.try_borrow_data()?)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        if now >= auction_info.end_time {
+            return Err(EscrowError::AuctionEnded.into());
+        }
+        let min_bid = auction_info.min_bid().ok_or(EscrowError::MathOverflow)?;
+        if bid < min_bid {
+            msg!("bid {} below the minimum bid {}", bid, min_bid);
+            return Err(EscrowError::BidTooLow.into());
+        }
+
+        //* escrow the new bid before refunding the previous one
+        invoke(
+            &transfer(bidder.key, auction_account.key, bid),
+            &[
+                bidder.clone(),
+                auction_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+        if auction_info.has_bid() {
+            let previous_bidder = next_account_info(account_info_iter)?;
+            if *previous_bidder.key != auction_info.highest_bidder {
+                return Err(EscrowError::BidderMismatch.into());
+            }
+            assert_writable(previous_bidder)?;
+            Self::transfer_lamports(auction_account, previous_bidder, auction_info.highest_bid)?;
+            msg!("refunded {} to {}", auction_info.highest_bid, previous_bidder.key);
+        }
+
+        //* a bid close to the end pushes it back so it can still be answered
+        if auction_info.extension_secs > 0 {
+            let extended_end_time = now
+                .checked_add(auction_info.extension_secs)
+                .ok_or(EscrowError::MathOverflow)?;
+            if extended_end_time > auction_info.end_time {
+                msg!("auction extended : {} -> {}", auction_info.end_time, extended_end_time);
+                auction_info.end_time = extended_end_time;
+            }
+        }
+
+        auction_info.highest_bidder = *bidder.key;
+        auction_info.highest_bid = bid;
+        AuctionState::pack(auction_info, &mut auction_account.try_borrow_mut_data()?)?;
+        msg!("auction info {:?}", auction_info);
+
+        Ok(())
+    }
+
+    //* Settle an ended english auction, permissionless: the tokens go to the
+    //* highest bidder and the bid is split between the seller and the treasury,
+    //* without a bid the tokens go back to the seller. A bid that can't be paid
+    //* out is refunded to the bidder once the refund grace period is over, the
+    //* tokens then go back to the seller. The listing and the auction are closed.
+    pub fn process_settle_auction(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let seller = next_account_info(account_info_iter)?;
+
+        let token_destination = next_account_info(account_info_iter)?;
+
+        let pda_account = next_account_info(account_info_iter)?;
+
+        let pdas_token_account = next_account_info(account_info_iter)?;
+
+        let auction_account = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let platform_state_account = next_account_info(account_info_iter)?;
+
+        let platform_treasury = next_account_info(account_info_iter)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+        let sellers_token_account = next_account_info(account_info_iter)?;
+
+        let highest_bidder = next_account_info(account_info_iter)?; // refunded bidder wallet
+
+        assert_token_program(token_program)?;
+        assert_writable(seller)?;
+        assert_writable(token_destination)?;
+        assert_writable(pda_account)?;
+        assert_writable(pdas_token_account)?;
+        assert_writable(auction_account)?;
+        assert_writable(token_state_account)?;
+        assert_writable(platform_treasury)?;
+
+        assert_owned_by(
+            platform_state_account,
+            program_id,
+            EscrowError::PlatformStateNotOwnedByProgram,
+        )?;
+        let platform_info = PlatformState::unpack(&platform_state_account.try_borrow_data()?)?;
+        if platform_info.treasury_account != *platform_treasury.key {
+            return Err(EscrowError::InvalidTreasuryAccount.into());
+        }
+        //* the secondary fee recipients of the platform come before the creators
+        let fee_recipient_accounts =
+            Self::fee_recipient_accounts(account_info_iter, &platform_info, None)?;
+        let mut token_state_info = Self::assert_token_state(
+            program_id,
+            platform_state_account,
+            token_mint,
+            token_state_account,
+        )?;
+
+        assert_owned_by(pda_account, program_id, EscrowError::ListingNotOwnedByProgram)?;
+        let lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+        if lister_info.seller_pubkey != *seller.key {
+            return Err(EscrowError::SellerMismatch.into());
+        }
+        let (pda, nonce) = ListerState::find_address(
+            program_id,
+            seller.key,
+            token_mint.key,
+            token_state_account.key,
+            lister_info.listing_id,
+        );
+        if pda != *pda_account.key {
+            return Err(EscrowError::InvalidListingPda.into());
+        }
+        if lister_info.token_account_pubkey != *pdas_token_account.key {
+            return Err(EscrowError::EscrowTokenAccountMismatch.into());
+        }
+
+        let (auction_pda, _nonce) = AuctionState::find_address(program_id, pda_account.key);
+        if auction_pda != *auction_account.key {
+            return Err(EscrowError::InvalidAuctionPda.into());
+        }
+        assert_owned_by(auction_account, program_id, EscrowError::AuctionNotOwnedByProgram)?;
+        let auction_info = AuctionState::unpack(&auction_account.try_borrow_data()?)?;
+        let now = Clock::get()?.unix_timestamp;
+        if now < auction_info.end_time {
+            return Err(EscrowError::AuctionNotEnded.into());
+        }
+
+        let mut token_destination = token_destination;
+        if auction_info.has_bid() {
+            let payout = Self::auction_payout(
+                account_info_iter,
+                &auction_info,
+                &platform_info,
+                token_state_info.enforce_royalties,
+                token_mint,
+                token_destination,
+                seller,
+                platform_treasury,
+                &fee_recipient_accounts,
+            );
+            let refund_after = auction_info
+                .end_time
+                .checked_add(AUCTION_REFUND_GRACE_SECS)
+                .ok_or(EscrowError::MathOverflow)?;
+            match payout {
+                //* pay out of the lamports escrowed in the auction
+                Ok(payments) => {
+                    for (account, amount) in payments {
+                        Self::transfer_lamports(auction_account, account, amount)?;
+                    }
+                }
+                //* past the grace period a stuck bid goes back to the bidder and the
+                //* tokens back to the seller
+                Err(error) if now >= refund_after => {
+                    msg!("payout failed: {}, bid refunded", error);
+                    if *highest_bidder.key != auction_info.highest_bidder {
+                        return Err(EscrowError::BidderMismatch.into());
+                    }
+                    assert_writable(highest_bidder)?;
+                    assert_token_account(
+                        sellers_token_account,
+                        seller.key,
+                        token_mint.key,
+                        EscrowError::InvalidSellerTokenAccount,
+                    )?;
+                    Self::transfer_lamports(
+                        auction_account,
+                        highest_bidder,
+                        auction_info.highest_bid,
+                    )?;
+                    token_destination = sellers_token_account;
+                }
+                Err(error) => return Err(error),
+            }
+        } else {
+            msg!("no bid, tokens go back to the seller");
+            assert_token_account(
+                token_destination,
+                seller.key,
+                token_mint.key,
+                EscrowError::InvalidSellerTokenAccount,
+            )?;
+        }
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            seller.key.as_ref(),
+            token_mint.key.as_ref(),
+            token_state_account.key.as_ref(),
+            &lister_info.listing_id.to_le_bytes(),
+            &[nonce],
+        ]];
+        Self::transfer_tokens(
+            token_program,
+            pdas_token_account,
+            token_mint,
+            token_destination,
+            pda_account,
+            lister_info.token_amount,
+            token_state_info.decimals,
+            signer_seeds,
+        )?;
+
+        token_state_info.total_no_of_tokens_listed = token_state_info
+            .total_no_of_tokens_listed
+            .checked_sub(lister_info.token_amount)
+            .ok_or(EscrowError::MathOverflow)?;
+        TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
+
+        Self::close_listing(
+            token_program,
+            pda_account,
+            pdas_token_account,
+            token_mint,
+            sellers_token_account,
+            seller,
+            token_state_info.decimals,
+            signer_seeds,
+        )?;
+
+        //* the auction rent goes back to the seller
+        Self::transfer_lamports(auction_account, seller, auction_account.lamports())?;
+        auction_account.try_borrow_mut_data()?.fill(0);
+
+        Ok(())
+    }
+
+    //* payments of the winning bid of an auction out of the auction PDA: the seller,
+    //* the treasury, the fee recipients and the creators. Royalties are capped at
+    //* the seller's share so no royalty rate can lock the bid in the auction.
+    #[allow(clippy::too_many_arguments)]
+    fn auction_payout<'a, 'b>(
+        account_info_iter: &mut Iter<'a, AccountInfo<'b>>,
+        auction_info: &AuctionState,
+        platform_info: &PlatformState,
+        enforce_royalties: bool,
+        token_mint: &AccountInfo,
+        token_destination: &AccountInfo,
+        seller: &'a AccountInfo<'b>,
+        platform_treasury: &'a AccountInfo<'b>,
+        fee_recipient_accounts: &[&'a AccountInfo<'b>],
+    ) -> Result<Vec<(&'a AccountInfo<'b>, u64)>, ProgramError> {
+        assert_token_account(
+            token_destination,
+            &auction_info.highest_bidder,
+            token_mint.key,
+            EscrowError::InvalidBuyerTokenAccount,
+        )?;
+
+        //* fee is rounded up, the seller gets the remainder
+        let (platform_fee, amount_expected_by_seller) =
+            split_fee(auction_info.highest_bid, platform_info.platform_fee_bps)?;
+        msg!("platform_fee {}", platform_fee);
+        let (treasury_fee, recipient_fees) = platform_info.split_platform_fee(platform_fee)?;
+
+        //* creator royalties are paid out of the seller's share
+        let royalties = if enforce_royalties {
+            Self::creator_royalties(
+                account_info_iter,
+                token_mint.key,
+                None,
+                auction_info.highest_bid,
+            )?
+        } else {
+            vec![]
+        };
+        let royalties = Self::cap_royalties(royalties, amount_expected_by_seller)?;
+        let amount_expected_by_seller =
+            Self::seller_proceeds(amount_expected_by_seller, &royalties)?;
+        msg!("amount_expected_by_seller {}", amount_expected_by_seller);
+
+        let payments = [
+            (seller, amount_expected_by_seller),
+            (platform_treasury, treasury_fee),
+        ];
+        let recipient_payments = fee_recipient_accounts.iter().copied().zip(recipient_fees);
+        Ok(payments
+            .into_iter()
+            .chain(recipient_payments)
+            .chain(royalties)
+            .collect())
+    }
+
+    //* Buy from many SOL quoted listings of a mint in one instruction, cheapest
+    //* first, up to a total amount and a lamport budget. Each listing passes its
+    //* listing, escrow token account, seller and seller token account, the creator
+    //* accounts shared by all the fills come last. Unfillable listings fail the
+    //* whole batch, or are skipped when `skip_unfillable` is set.
+    #[allow(clippy::too_many_arguments)]
+    pub fn process_batch_exchange(
+        accounts: &[AccountInfo],
+        fills: Vec<BatchFill>,
+        unit: AmountUnit,
+        max_amount: u64,
+        max_price_per_token: u64,
+        max_total_price: u64,
+        skip_unfillable: bool,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let taker = next_account_info(account_info_iter)?;
+
+        assert_signer(taker, EscrowError::TakerNotSigner)?;
+
+        let takers_token_account = next_account_info(account_info_iter)?;
+
+        let platform_state_account = next_account_info(account_info_iter)?;
+
+        let platform_treasury = next_account_info(account_info_iter)?;
+
+        let token_mint = next_account_info(account_info_iter)?;
+
+        let token_state_account = next_account_info(account_info_iter)?;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+        let system_program = next_account_info(account_info_iter)?;
+
+        assert_token_program(token_program)?;
+        assert_system_program(system_program)?;
+        assert_writable(taker)?;
+        assert_writable(takers_token_account)?;
+        assert_writable(platform_treasury)?;
+        assert_writable(token_state_account)?;
+
+        //* the platform state is read once for the whole batch
+        assert_owned_by(
+            platform_state_account,
+            program_id,
+            EscrowError::PlatformStateNotOwnedByProgram,
+        )?;
+        let platform_info = PlatformState::unpack(&platform_state_account.try_borrow_data()?)?;
+        if platform_info.treasury_account != *platform_treasury.key {
+            return Err(EscrowError::InvalidTreasuryAccount.into());
+        }
+        let mut token_state_info = Self::assert_token_state(
+            program_id,
+            platform_state_account,
+            token_mint,
+            token_state_account,
+        )?;
+        assert_token_account_mint(
+            takers_token_account,
+            token_mint.key,
+            EscrowError::InvalidTakerTokenAccount,
+        )?;
+
+        //* the secondary fee recipients of the platform come before the listings
+        let fee_recipient_accounts =
+            Self::fee_recipient_accounts(account_info_iter, &platform_info, None)?;
+
+        let mut listings = Vec::with_capacity(fills.len());
+        for fill in fills {
+            let pda_account = next_account_info(account_info_iter)?;
+            let pdas_token_account = next_account_info(account_info_iter)?;
+            let seller = next_account_info(account_info_iter)?;
+            let sellers_token_account = next_account_info(account_info_iter)?;
+            listings.push((
+                fill,
+                pda_account,
+                pdas_token_account,
+                seller,
+                sellers_token_account,
+            ));
+        }
+        let royalty_accounts = account_info_iter.as_slice();
+
+        //* cheapest listings first, the ones that can't be read last. The sort is
+        //* stable, listings of the same price keep the order they were passed in.
+        let now = Clock::get()?.unix_timestamp;
+        listings.sort_by_cached_key(|(_, pda_account, _, _, _)| {
+            pda_account
+                .try_borrow_data()
+                .ok()
+                .and_then(|data| ListerState::unpack(&data).ok())
+                .and_then(|lister_info| lister_info.current_price(now).ok())
+                .unwrap_or(u64::MAX)
+        });
+
+        let max_amount = unit.to_base_units(max_amount, token_state_info.decimals)?;
+        let mut remaining_amount = max_amount;
+        let mut remaining_budget = max_total_price;
+        let mut filled_listings = 0u64;
+        for (fill, pda_account, pdas_token_account, seller, sellers_token_account) in listings {
+            if remaining_amount == 0 {
+                break;
+            }
+
+            //* every check of a listing happens before anything moves, so a
+            //* skipped listing leaves no partial transfer behind
+            let fill_amount = unit.to_base_units(fill.amount, token_state_info.decimals)?;
+            let plan = Self::plan_batch_fill(
+                program_id,
+                &token_state_info,
+                token_mint,
+                token_state_account,
+                pda_account,
+                pdas_token_account,
+                seller,
+                fill.listing_id,
+                fill_amount.min(remaining_amount),
+                max_price_per_token,
+                remaining_budget,
+                now,
+            );
+            let (mut lister_info, nonce, amount, total) = match plan {
+                Ok(plan) => plan,
+                Err(error) if skip_unfillable => {
+                    msg!("listing {} skipped : {:?}", pda_account.key, error);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            msg!("listing {} filled {} for {}", pda_account.key, amount, total);
+
+            //* fee is rounded up, the seller gets the remainder
+            let (platform_fee, amount_expected_by_seller) =
+                split_fee(total, platform_info.platform_fee_bps)?;
+            let (treasury_fee, recipient_fees) = platform_info.split_platform_fee(platform_fee)?;
+
+            //* creator royalties are paid out of the seller's share
+            let royalties = if token_state_info.enforce_royalties {
+                Self::creator_royalties(
+                    &mut royalty_accounts.iter(),
+                    token_mint.key,
+                    None,
+                    total,
+                )?
+            } else {
+                vec![]
+            };
+            let amount_expected_by_seller =
+                Self::seller_proceeds(amount_expected_by_seller, &royalties)?;
+
+            let payments = [
+                (seller, amount_expected_by_seller),
+                (platform_treasury, treasury_fee),
+            ];
+            let recipient_payments = fee_recipient_accounts.iter().copied().zip(recipient_fees);
+            for (account, amount) in payments
+                .into_iter()
+                .chain(recipient_payments)
+                .chain(royalties)
+            {
+                invoke(
+                    &transfer(taker.key, account.key, amount),
+                    &[taker.clone(), account.clone(), system_program.clone()],
+                )?;
+            }
+
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                seller.key.as_ref(),
+                token_mint.key.as_ref(),
+                token_state_account.key.as_ref(),
+                &fill.listing_id.to_le_bytes(),
+                &[nonce],
+            ]];
+            Self::transfer_tokens(
+                token_program,
+                pdas_token_account,
+                token_mint,
+                takers_token_account,
+                pda_account,
+                amount,
+                token_state_info.decimals,
+                signer_seeds,
+            )?;
+
+            lister_info.token_amount = lister_info
+                .token_amount
+                .checked_sub(amount)
+                .ok_or(EscrowError::InsufficientListedTokens)?;
+            token_state_info.total_no_of_tokens_listed = token_state_info
+                .total_no_of_tokens_listed
+                .checked_sub(amount)
+                .ok_or(EscrowError::MathOverflow)?;
+            if lister_info.token_amount == 0 {
+                Self::close_listing(
+                    token_program,
+                    pda_account,
+                    pdas_token_account,
+                    token_mint,
+                    sellers_token_account,
+                    seller,
+                    token_state_info.decimals,
+                    signer_seeds,
+                )?;
+            } else {
+                ListerState::pack(lister_info, &mut pda_account.try_borrow_mut_data()?)?;
+            }
+
+            remaining_amount -= amount;
+            remaining_budget -= total;
+            filled_listings += 1;
+        }
+
+        TokenState::pack(token_state_info, &mut token_state_account.try_borrow_mut_data()?)?;
+
+        msg!(
+            "batch filled {} tokens from {} listings for {}",
+            max_amount - remaining_amount,
+            filled_listings,
+            max_total_price - remaining_budget
+        );
+        if filled_listings == 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+
+        Ok(())
+    }
+
+    //* create and initialize the token state PDA of `token_mint`, funded by `payer`
+    fn create_token_state_account<'a>(
+        payer: &AccountInfo<'a>,
+        platfrom_account: &AccountInfo<'a>,
+        token_state_account: &AccountInfo<'a>,
+        token_mint: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        nft_mode: bool,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        assert_writable(token_state_account)?;
+        assert_system_program(system_program)?;
+
+        //* read the mint so amounts can be scaled by its decimals
+        assert_owned_by(token_mint, &spl_token::id(), EscrowError::InvalidMint)?;
+        let mint_info = Mint::unpack(&token_mint.try_borrow_data()?)
+            .map_err(|_| EscrowError::InvalidMint)?;
+
+        //* Create pda for token state account
+        let (token_pda, nonce) =
+            TokenState::find_address(program_id, platfrom_account.key, token_mint.key);
+
+        if token_pda != *token_state_account.key {
+            return Err(EscrowError::InvalidTokenStatePda.into());
+        }
+
+        //* Create a new account for token state*/
+        invoke_signed(
+            &create_account(
+                payer.key,
+                token_state_account.key,
+                Rent::default().minimum_balance(TokenState::LEN),
+                TokenState::LEN as u64,
+                program_id,
+            ),
+            &[
+                payer.clone(),
+                token_state_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                (platfrom_account.key).as_ref(),
+                (token_mint.key).as_ref(),
+                &[nonce],
+            ]],
+        )?;
+
+        assert_owned_by(token_state_account, program_id, EscrowError::TokenStateNotOwnedByProgram)?;
+
+        //* unpack the platfrom_account state, to store data into
+        let mut token_info = TokenState::unpack_unchecked(&token_state_account.try_borrow_data()?)?;
+
+        token_info.is_initialized = true;
+        token_info.owner_pubkey = *payer.key;
+        token_info.total_no_of_tokens_listed = 0;
+        token_info.token_mint = *token_mint.key;
+        token_info.decimals = mint_info.decimals;
+        token_info.enforce_royalties = false;
+        token_info.nft_mode = nft_mode;
+
+        //* pack data into the platform account
+        TokenState::pack(token_info, &mut token_state_account.try_borrow_mut_data()?)?;
+
+        msg!("Token_state_info : {:?}", token_info);
+
+        Ok(())
+    }
+
+    //* check that the NFT `token_mint` belongs to an allowed collection: the mint
+    //* metadata has to name the collection mint of the collection state as its
+    //* verified collection
+    fn assert_collection_allowed(
+        platform_state_account: &AccountInfo,
+        token_mint: &Pubkey,
+        metadata_account: &AccountInfo,
+        collection_state_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        if *metadata_account.key != find_metadata_address(token_mint)
+            || *metadata_account.owner != metaplex_token_metadata::id()
+        {
+            return Err(EscrowError::InvalidMetadataAccount.into());
+        }
+        let (metadata, collection) = unpack_metadata(&metadata_account.try_borrow_data()?)?;
+        if metadata.mint != *token_mint {
+            return Err(EscrowError::InvalidMetadataAccount.into());
+        }
+
+        //* removed collections have no collection state left
+        assert_owned_by(
+            collection_state_account,
+            program_id,
+            EscrowError::CollectionNotAllowed,
+        )?;
+        let collection_info =
+            CollectionState::unpack(&collection_state_account.try_borrow_data()?)
+                .map_err(|_| EscrowError::CollectionNotAllowed)?;
+        let (collection_pda, _nonce) = CollectionState::find_address(
+            program_id,
+            platform_state_account.key,
+            &collection_info.collection_mint,
+        );
+        if collection_pda != *collection_state_account.key {
+            return Err(EscrowError::InvalidCollectionStatePda.into());
+        }
+
+        //* only collections verified by their collection authority count
+        match collection {
+            Some(collection)
+                if collection.verified && collection.key == collection_info.collection_mint => {}
+            _ => return Err(EscrowError::CollectionNotAllowed.into()),
+        }
+        Ok(())
+    }
+
+    //* token balance held by an escrow token account
+    fn escrow_balance(pdas_token_account: &AccountInfo) -> Result<u64, ProgramError> {
+        Ok(TokenAccount::unpack(&pdas_token_account.try_borrow_data()?)?.amount)
+    }
+
+    //* royalties owed to the verified creators of `token_mint` on a sale of `total`,
+    //* read from its Metaplex metadata account followed by one account per verified
+    //* creator: the creator wallet, or its quote token account for SPL quoted listings.
+    //* Mints without metadata pay no royalties.
+    fn creator_royalties<'a, 'b>(
+        account_info_iter: &mut Iter<'a, AccountInfo<'b>>,
+        token_mint: &Pubkey,
+        quote_mint: Option<&Pubkey>,
+        total: u64,
+    ) -> Result<Vec<(&'a AccountInfo<'b>, u64)>, ProgramError> {
+        let metadata_account = next_account_info(account_info_iter)?;
+
+        if *metadata_account.key != find_metadata_address(token_mint) {
+            return Err(EscrowError::InvalidMetadataAccount.into());
+        }
+        if *metadata_account.owner != metaplex_token_metadata::id()
+            || metadata_account.data_is_empty()
+        {
+            msg!("no metadata for the mint, no royalties");
+            return Ok(vec![]);
+        }
+
+        let metadata = Metadata::from_account_info(metadata_account)?;
+        if metadata.mint != *token_mint {
+            return Err(EscrowError::InvalidMetadataAccount.into());
+        }
+
+        let royalty = fee_amount(total, metadata.data.seller_fee_basis_points as u64)?;
+        let mut royalties = vec![];
+        for creator in metadata.data.creators.unwrap_or_default() {
+            if !creator.verified {
+                continue;
+            }
+            let creator_account = next_account_info(account_info_iter)?;
+            match quote_mint {
+                Some(quote_mint) => assert_token_account(
+                    creator_account,
+                    &creator.address,
+                    quote_mint,
+                    EscrowError::CreatorMismatch,
+                )?,
+                None if *creator_account.key != creator.address => {
+                    return Err(EscrowError::CreatorMismatch.into());
+                }
+                None => {}
+            }
+            assert_writable(creator_account)?;
+
+            let amount = creator_royalty(royalty, creator.share)?;
+            msg!("royalty {} to {}", amount, creator.address);
+            if amount > 0 {
+                royalties.push((creator_account, amount));
+            }
+        }
+
+        Ok(royalties)
+    }
+
+    //* sum of the creator royalties of a sale
+    fn total_royalties(royalties: &[(&AccountInfo, u64)]) -> Result<u64, ProgramError> {
+        royalties
+            .iter()
+            .try_fold(0u64, |total, (_, royalty)| total.checked_add(*royalty))
+            .ok_or_else(|| EscrowError::MathOverflow.into())
+    }
+
+    //* scale the creator royalties down to `seller_share` when they add up to more
+    fn cap_royalties<'a, 'b>(
+        royalties: Vec<(&'a AccountInfo<'b>, u64)>,
+        seller_share: u64,
+    ) -> Result<Vec<(&'a AccountInfo<'b>, u64)>, ProgramError> {
+        let total_royalties = Self::total_royalties(&royalties)?;
+        if total_royalties <= seller_share {
+            return Ok(royalties);
+        }
+        msg!("royalties {} capped at {}", total_royalties, seller_share);
+        royalties
+            .into_iter()
+            .map(|(account, royalty)| {
+                Ok((account, capped_royalty(royalty, total_royalties, seller_share)?))
+            })
+            .collect()
+    }
+
+    //* what is left of the seller's share of a sale once the creator royalties
+    //* are paid out of it
+    fn seller_proceeds(
+        seller_share: u64,
+        royalties: &[(&AccountInfo, u64)],
+    ) -> Result<u64, ProgramError> {
+        let total_royalties = Self::total_royalties(royalties)?;
+        msg!("total_royalties {}", total_royalties);
+        seller_share
+            .checked_sub(total_royalties)
+            .ok_or_else(|| EscrowError::RoyaltiesExceedProceeds.into())
+    }
+
+    //* accounts of the secondary fee recipients of the platform, in config order:
+    //* the recipient wallet, or its quote token account for SPL quoted listings
+    fn fee_recipient_accounts<'a, 'b>(
+        account_info_iter: &mut Iter<'a, AccountInfo<'b>>,
+        platform_info: &PlatformState,
+        quote_mint: Option<&Pubkey>,
+    ) -> Result<Vec<&'a AccountInfo<'b>>, ProgramError> {
+        let mut accounts = vec![];
+        for recipient in platform_info.secondary_fee_recipients() {
+            let recipient_account = next_account_info(account_info_iter)?;
+            match quote_mint {
+                Some(quote_mint) => assert_token_account(
+                    recipient_account,
+                    &recipient.account,
+                    quote_mint,
+                    EscrowError::FeeRecipientMismatch,
+                )?,
+                None if *recipient_account.key != recipient.account => {
+                    return Err(EscrowError::FeeRecipientMismatch.into());
+                }
+                None => {}
+            }
+            assert_writable(recipient_account)?;
+            accounts.push(recipient_account);
+        }
+        Ok(accounts)
+    }
+
+    //* close a listing once its listed tokens are gone: any leftover of the escrow
+    //* goes to the seller token account, the escrow token account is closed by the
+    //* listing PDA and the listing state is drained, both rents go back to the seller
+    #[allow(clippy::too_many_arguments)]
+    fn close_listing<'a>(
+        token_program: &AccountInfo<'a>,
+        pda_account: &AccountInfo<'a>,
+        pdas_token_account: &AccountInfo<'a>,
+        token_mint: &AccountInfo<'a>,
+        seller_token_account: &AccountInfo<'a>,
+        seller: &AccountInfo<'a>,
+        decimals: u8,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        //* tokens sent to the escrow outside of the program are swept to the seller
+        //* first, close_account only takes an empty token account
+        let leftover = Self::escrow_balance(pdas_token_account)?;
+        if leftover > 0 {
+            assert_writable(seller_token_account)?;
+            assert_token_account(
+                seller_token_account,
+                seller.key,
+                token_mint.key,
+                EscrowError::InvalidSellerTokenAccount,
+            )?;
+            Self::transfer_tokens(
+                token_program,
+                pdas_token_account,
+                token_mint,
+                seller_token_account,
+                pda_account,
+                leftover,
+                decimals,
+                signer_seeds,
+            )?;
+            msg!("escrow leftover {} swept to the seller", leftover);
+        }
+        invoke_signed(
+            &spl_token::instruction::close_account(
+                token_program.key,
+                pdas_token_account.key,
+                seller.key,
+                pda_account.key,
+                &[],
+            )?,
+            &[
+                pdas_token_account.clone(),
+                seller.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            signer_seeds,
+        )?;
+
+        let listing_lamports = pda_account.lamports();
+        **seller.try_borrow_mut_lamports()? = seller
+            .lamports()
+            .checked_add(listing_lamports)
+            .ok_or(EscrowError::MathOverflow)?;
+        **pda_account.try_borrow_mut_lamports()? = 0;
+        pda_account.try_borrow_mut_data()?.fill(0);
+
+        Ok(())
+    }
+
+    //* check that a listing of a BatchExchange can be filled and size the fill:
+    //* returns the listing, the bump of its PDA, the amount bought and its price
+    #[allow(clippy::too_many_arguments)]
+    fn plan_batch_fill(
+        program_id: &Pubkey,
+        token_state_info: &TokenState,
+        token_mint: &AccountInfo,
+        token_state_account: &AccountInfo,
+        pda_account: &AccountInfo,
+        pdas_token_account: &AccountInfo,
+        seller: &AccountInfo,
+        listing_id: u64,
+        amount: u64,
+        max_price_per_token: u64,
+        budget: u64,
+        now: i64,
+    ) -> Result<(ListerState, u8, u64, u64), ProgramError> {
+        let (pda, nonce) = ListerState::find_address(
+            program_id,
+            seller.key,
+            token_mint.key,
+            token_state_account.key,
+            listing_id,
+        );
+        if pda != *pda_account.key {
+            return Err(EscrowError::InvalidListingPda.into());
+        }
+        assert_owned_by(pda_account, program_id, EscrowError::ListingNotOwnedByProgram)?;
+        assert_writable(pda_account)?;
+        assert_writable(pdas_token_account)?;
+        assert_writable(seller)?;
+        let lister_info = ListerState::unpack(&pda_account.try_borrow_data()?)?;
+
+        if lister_info.english_auction {
+            return Err(EscrowError::EnglishAuctionListing.into());
+        }
+        if lister_info.is_expired(now) {
+            return Err(EscrowError::ListingExpired.into());
+        }
+        //* the batch budget is in lamports
+        if lister_info.quote_mint != Pubkey::default() {
+            return Err(EscrowError::QuoteMintMismatch.into());
+        }
+        if lister_info.seller_pubkey != *seller.key {
+            return Err(EscrowError::SellerMismatch.into());
+        }
+        if lister_info.token_account_pubkey != *pdas_token_account.key {
+            return Err(EscrowError::EscrowTokenAccountMismatch.into());
+        }
+
+        let price_per_token = lister_info.current_price(now)?;
+        if price_per_token > max_price_per_token {
+            return Err(EscrowError::PriceAboveLimit.into());
+        }
+
+        let affordable = affordable_amount(budget, price_per_token, token_state_info.decimals)?;
+        if affordable == 0 {
+            return Err(EscrowError::TotalPriceAboveLimit.into());
+        }
+        let amount = amount.min(lister_info.token_amount).min(affordable);
+        if amount == 0 {
+            return Err(EscrowError::InvalidAmount.into());
+        }
+        //* NFT listings are sold whole
+        if token_state_info.nft_mode && amount != lister_info.token_amount {
+            return Err(EscrowError::PartialFillNotAllowed.into());
+        }
+
+        let total = total_price(amount, price_per_token, token_state_info.decimals)?;
+        Ok((lister_info, nonce, amount, total))
+    }
+
+    //* add `amount` to the earnings of `referrer` for trades quoted in `quote_mint`,
+    //* the referrer state PDA is created on the first referral, funded by `payer`
+    #[allow(clippy::too_many_arguments)]
+    fn record_referral<'a>(
+        program_id: &Pubkey,
+        platform_state_account: &AccountInfo<'a>,
+        referrer: &AccountInfo<'a>,
+        referrer_state_account: &AccountInfo<'a>,
+        quote_mint: &Pubkey,
+        payer: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        amount: u64,
+    ) -> ProgramResult {
+        assert_writable(referrer_state_account)?;
+        let (referrer_pda, nonce) = ReferrerState::find_address(
+            program_id,
+            platform_state_account.key,
+            referrer.key,
+            quote_mint,
+        );
+        if referrer_pda != *referrer_state_account.key {
+            return Err(EscrowError::InvalidReferrerStatePda.into());
+        }
+
+        if referrer_state_account.owner != program_id {
+            invoke_signed(
+                &create_account(
+                    payer.key,
+                    referrer_state_account.key,
+                    Rent::default().minimum_balance(ReferrerState::LEN),
+                    ReferrerState::LEN as u64,
+                    program_id,
+                ),
+                &[
+                    payer.clone(),
+                    referrer_state_account.clone(),
+                    system_program.clone(),
+                ],
+                &[&[
+                    b"referrer",
+                    platform_state_account.key.as_ref(),
+                    referrer.key.as_ref(),
+                    quote_mint.as_ref(),
+                    &[nonce],
+                ]],
+            )?;
+        }
+
+        let mut referrer_info =
+            ReferrerState::unpack_unchecked(&referrer_state_account.try_borrow_data()?)?;
+        referrer_info.is_initialized = true;
+        referrer_info.referrer = *referrer.key;
+        referrer_info.quote_mint = *quote_mint;
+        referrer_info.total_earned = referrer_info
+            .total_earned
+            .checked_add(amount)
+            .ok_or(EscrowError::MathOverflow)?;
+        ReferrerState::pack(referrer_info, &mut referrer_state_account.try_borrow_mut_data()?)?;
+        msg!("referral {} to {}", amount, referrer.key);
+
+        Ok(())
+    }
+
+    //* check that `token_state_account` is the token state of `token_mint` on the platform
+    fn assert_token_state(
+        program_id: &Pubkey,
+        platform_state_account: &AccountInfo,
+        token_mint: &AccountInfo,
+        token_state_account: &AccountInfo,
+    ) -> Result<TokenState, ProgramError> {
+        assert_owned_by(
+            token_state_account,
+            program_id,
+            EscrowError::TokenStateNotOwnedByProgram,
+        )?;
+        let (token_pda, _nonce) =
+            TokenState::find_address(program_id, platform_state_account.key, token_mint.key);
+        if token_pda != *token_state_account.key {
+            return Err(EscrowError::InvalidTokenStatePda.into());
+        }
+        let token_state_info = TokenState::unpack(&token_state_account.try_borrow_data()?)?;
+        if token_state_info.token_mint != *token_mint.key {
+            return Err(EscrowError::TokenMintMismatch.into());
+        }
+        Ok(token_state_info)
+    }
+
+    //* unpack the order book of a token state after checking it and its vault,
+    //* returns the bump of the book PDA for signing
+    fn unpack_order_book(
+        program_id: &Pubkey,
+        token_state_account: &AccountInfo,
+        order_book_account: &AccountInfo,
+        vault_token_account: &AccountInfo,
+        token_mint: &AccountInfo,
+    ) -> Result<(OrderBook, u8), ProgramError> {
+        let (order_book_pda, nonce) = OrderBook::find_address(program_id, token_state_account.key);
+        if order_book_pda != *order_book_account.key {
+            return Err(EscrowError::InvalidOrderBookPda.into());
+        }
+        assert_owned_by(
+            order_book_account,
+            program_id,
+            EscrowError::OrderBookNotOwnedByProgram,
+        )?;
+        if *vault_token_account.key
+            != get_associated_token_address(order_book_account.key, token_mint.key)
+        {
+            return Err(EscrowError::InvalidEscrowTokenAccount.into());
+        }
+        let order_book = OrderBook::unpack(&order_book_account.try_borrow_data()?)?;
+        Ok((order_book, nonce))
+    }
+
+    //* associated token account of `owner` for `token_mint`, created and paid by
+    //* `payer` when it does not exist, so a closed account never blocks a fill
+    #[allow(clippy::too_many_arguments)]
+    fn owner_token_account<'a>(
+        payer: &AccountInfo<'a>,
+        owner: &AccountInfo<'a>,
+        owner_token_account: &AccountInfo<'a>,
+        token_mint: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        rent_sysvar: &AccountInfo<'a>,
+        associated_token_program: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        if *owner_token_account.key != get_associated_token_address(owner.key, token_mint.key) {
+            return Err(EscrowError::CounterpartyMismatch.into());
+        }
+        assert_writable(owner_token_account)?;
+        if owner_token_account.data_is_empty() {
+            invoke(
+                &create_associated_token_account(payer.key, owner.key, token_mint.key),
+                &[
+                    payer.clone(),
+                    owner_token_account.clone(),
+                    owner.clone(),
+                    token_mint.clone(),
+                    system_program.clone(),
+                    token_program.clone(),
+                    rent_sysvar.clone(),
+                    associated_token_program.clone(),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    //* lamports released from an escrow of `quantity` tokens at `price_per_token`
+    //* when `fill` of them are sold: the difference of the totals before and after,
+    //* so the fills of an escrow add up to exactly the escrowed amount
+    fn escrow_released(
+        quantity: u64,
+        fill: u64,
+        price_per_token: u64,
+        decimals: u8,
+    ) -> Result<u64, ProgramError> {
+        let remaining = quantity.checked_sub(fill).ok_or(EscrowError::MathOverflow)?;
+        total_price(quantity, price_per_token, decimals)?
+            .checked_sub(total_price(remaining, price_per_token, decimals)?)
+            .ok_or_else(|| EscrowError::MathOverflow.into())
+    }
+
+    //* give the escrow of a resting order taken off the book back like a cancel: the
+    //* lamports of a bid to its owner, the tokens of an ask to the associated token
+    //* account of its owner, created by `payer` if missing
+    #[allow(clippy::too_many_arguments)]
+    fn refund_order<'a>(
+        side: Side,
+        order: &Order,
+        owner: &AccountInfo<'a>,
+        owner_token_account: &AccountInfo<'a>,
+        payer: &AccountInfo<'a>,
+        order_book_account: &AccountInfo<'a>,
+        vault_token_account: &AccountInfo<'a>,
+        token_mint: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        token_program: &AccountInfo<'a>,
+        rent_sysvar: &AccountInfo<'a>,
+        associated_token_program: &AccountInfo<'a>,
+        decimals: u8,
+        book_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        match side {
+            Side::Bid => {
+                assert_writable(owner)?;
+                let escrow_amount = total_price(order.quantity, order.price_per_token, decimals)?;
+                Self::transfer_lamports(order_book_account, owner, escrow_amount)
+            }
+            Side::Ask => {
+                Self::owner_token_account(
+                    payer,
+                    owner,
+                    owner_token_account,
+                    token_mint,
+                    system_program,
+                    token_program,
+                    rent_sysvar,
+                    associated_token_program,
+                )?;
+                Self::transfer_tokens(
+                    token_program,
+                    vault_token_account,
+                    token_mint,
+                    owner_token_account,
+                    order_book_account,
+                    order.quantity,
+                    decimals,
+                    &[book_seeds],
+                )
+            }
+        }
+    }
+
+    //* move lamports out of an account owned by the program
+    fn transfer_lamports(
+        source: &AccountInfo,
+        destination: &AccountInfo,
+        amount: u64,
+    ) -> ProgramResult {
+        **source.try_borrow_mut_lamports()? = source
+            .lamports()
+            .checked_sub(amount)
+            .ok_or(EscrowError::MathOverflow)?;
+        **destination.try_borrow_mut_lamports()? = destination
+            .lamports()
+            .checked_add(amount)
+            .ok_or(EscrowError::MathOverflow)?;
+        Ok(())
+    }
+
+    //* close an offer, the escrowed lamports left and the rent go back to the buyer
+    fn close_offer(offer_account: &AccountInfo, buyer: &AccountInfo) -> ProgramResult {
+        Self::transfer_lamports(offer_account, buyer, offer_account.lamports())?;
+        offer_account.try_borrow_mut_data()?.fill(0);
+        Ok(())
+    }
+
+    //* move `amount` tokens with transfer_checked, `signer_seeds` is empty
+    //* when the authority signed the transaction itself
+    #[allow(clippy::too_many_arguments)]
+    fn transfer_tokens<'a>(
+        token_program: &AccountInfo<'a>,
+        source: &AccountInfo<'a>,
+        mint: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        authority: &AccountInfo<'a>,
+        amount: u64,
+        decimals: u8,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let tranfer_instructions = spl_token::instruction::transfer_checked(
+            token_program.key,
+            source.key,
+            mint.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        )?;
+        invoke_signed(
+            &tranfer_instructions,
+            &[
+                source.clone(),
+                mint.clone(),
+                destination.clone(),
+                authority.clone(),
+                token_program.clone(),
+            ],
+            signer_seeds,
+        )
+    }
+
+    //* check that `admin` is the platform admin and signed the instruction
+    fn assert_admin(admin: &AccountInfo, platform_info: &PlatformState) -> ProgramResult {
+        if platform_info.admin != *admin.key {
+            msg!("Invalid admin.....");
+            return Err(EscrowError::InvalidAdmin.into());
+        }
+        assert_signer(admin, EscrowError::AdminNotSigner)?;
         Ok(())
     }
 }
@@ -1,3 +1,5 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use metaplex_token_metadata::state::{Key, Metadata};
 use solana_program::{
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
@@ -6,11 +8,101 @@ use solana_program::{
 
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 
+use crate::{
+    error::EscrowError,
+    fees::{dutch_auction_price, fee_share, BPS_DENOMINATOR},
+};
+
+/// Number of SPL quote mints a platform can allow besides SOL
+pub const MAX_QUOTE_MINTS: usize = 4;
+
+/// Number of accounts the platform fee can be split between
+pub const MAX_FEE_RECIPIENTS: usize = 4;
+
+/// Time after the end of an english auction past which a bid that can't be
+/// paid out is refunded to the bidder instead of locking the auction
+pub const AUCTION_REFUND_GRACE_SECS: i64 = 7 * 24 * 60 * 60;
+
+const FEE_RECIPIENT_LEN: usize = 40;
+
+/// Account receiving `weight_bps` basis points of the platform fee
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq, Copy, Clone)]
+pub struct FeeRecipient {
+    pub account: Pubkey, // wallet, default pubkey for an empty slot
+    pub weight_bps: u64, // share of the platform fee in basis points
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct PlatformState {
     pub is_initialized: bool,
     pub treasury_account: Pubkey,
-    pub platform_fess: u64,
+    pub platform_fee_bps: u64, // platform fee in basis points
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey, // proposed admin, default pubkey when there is none
+    pub quote_mints: [Pubkey; MAX_QUOTE_MINTS], // allowed SPL quote mints, default when empty
+    pub referral_share_bps: u64, // share of the platform fee paid to referrers, in basis points
+    pub fee_recipients: [FeeRecipient; MAX_FEE_RECIPIENTS], // split of the fee, empty for none
+}
+
+impl PlatformState {
+    /// SOL (the default pubkey) is always allowed as quote
+    pub fn is_quote_mint_allowed(&self, quote_mint: &Pubkey) -> bool {
+        *quote_mint == Pubkey::default() || self.quote_mints.contains(quote_mint)
+    }
+
+    /// Configured fee recipients, none when the whole fee goes to the treasury
+    pub fn fee_recipients(&self) -> impl Iterator<Item = &FeeRecipient> {
+        self.fee_recipients
+            .iter()
+            .filter(|recipient| recipient.account != Pubkey::default())
+    }
+
+    /// Fee recipients paid besides the treasury account, in config order
+    pub fn secondary_fee_recipients(&self) -> impl Iterator<Item = &FeeRecipient> {
+        let treasury_account = self.treasury_account;
+        self.fee_recipients()
+            .filter(move |recipient| recipient.account != treasury_account)
+    }
+
+    /// A fee split pays distinct accounts, the treasury account among them,
+    /// with non-zero weights summing to the whole fee
+    pub fn is_valid_fee_split(&self) -> bool {
+        let recipients = self.fee_recipients().collect::<Vec<_>>();
+        if recipients.is_empty() {
+            return true;
+        }
+        let total_weight = recipients.iter().try_fold(0u64, |total, recipient| {
+            match recipient.weight_bps {
+                0 => None,
+                weight_bps => total.checked_add(weight_bps),
+            }
+        });
+        let distinct = recipients.iter().enumerate().all(|(index, recipient)| {
+            recipients[..index]
+                .iter()
+                .all(|other| other.account != recipient.account)
+        });
+        total_weight == Some(BPS_DENOMINATOR)
+            && distinct
+            && recipients
+                .iter()
+                .any(|recipient| recipient.account == self.treasury_account)
+    }
+
+    /// Splits `platform_fee` into the share of the treasury account and the
+    /// shares of the secondary fee recipients. Shares are rounded down, the
+    /// treasury takes the rounding dust, and all of the fee without a split.
+    pub fn split_platform_fee(&self, platform_fee: u64) -> Result<(u64, Vec<u64>), ProgramError> {
+        let shares = self
+            .secondary_fee_recipients()
+            .map(|recipient| fee_share(platform_fee, recipient.weight_bps))
+            .collect::<Result<Vec<_>, _>>()?;
+        let treasury_share = shares
+            .iter()
+            .try_fold(platform_fee, |rest, share| rest.checked_sub(*share))
+            .ok_or(EscrowError::MathOverflow)?;
+        Ok((treasury_share, shares))
+    }
 }
 
 impl Sealed for PlatformState {}
@@ -20,34 +112,109 @@ impl IsInitialized for PlatformState {
     }
 }
 impl Pack for PlatformState {
-    const LEN: usize = 41;
+    const LEN: usize = 401;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, PlatformState::LEN];
-        let (is_initialized, treasury_account, platform_fess) = array_refs![src, 1, 32, 8];
+        let (
+            is_initialized,
+            treasury_account,
+            platform_fee_bps,
+            admin,
+            pending_admin,
+            quote_mints_src,
+            referral_share_bps,
+            fee_recipients_src,
+        ) = array_refs![
+            src,
+            1,
+            32,
+            8,
+            32,
+            32,
+            32 * MAX_QUOTE_MINTS,
+            8,
+            FEE_RECIPIENT_LEN * MAX_FEE_RECIPIENTS
+        ];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
             _ => return Err(ProgramError::InvalidAccountData),
         };
+        let mut quote_mints = [Pubkey::default(); MAX_QUOTE_MINTS];
+        for (quote_mint, src) in quote_mints.iter_mut().zip(quote_mints_src.chunks(32)) {
+            *quote_mint = Pubkey::new(src);
+        }
+        let mut fee_recipients = [FeeRecipient::default(); MAX_FEE_RECIPIENTS];
+        for (recipient, src) in fee_recipients
+            .iter_mut()
+            .zip(fee_recipients_src.chunks(FEE_RECIPIENT_LEN))
+        {
+            let (account, weight_bps) = array_refs![array_ref![src, 0, FEE_RECIPIENT_LEN], 32, 8];
+            recipient.account = Pubkey::new_from_array(*account);
+            recipient.weight_bps = u64::from_le_bytes(*weight_bps);
+        }
         Ok(PlatformState {
             is_initialized,
             treasury_account: Pubkey::new_from_array(*treasury_account),
-            platform_fess: u64::from_le_bytes(*platform_fess),
+            platform_fee_bps: u64::from_le_bytes(*platform_fee_bps),
+            admin: Pubkey::new_from_array(*admin),
+            pending_admin: Pubkey::new_from_array(*pending_admin),
+            quote_mints,
+            referral_share_bps: u64::from_le_bytes(*referral_share_bps),
+            fee_recipients,
         })
     }
 
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let dst = array_mut_ref![dst, 0, PlatformState::LEN];
-        let (is_initialized_dst, treasury_account_dst, base_percentage_dst) =
-            mut_array_refs![dst, 1, 32, 8];
+        let (
+            is_initialized_dst,
+            treasury_account_dst,
+            platform_fee_bps_dst,
+            admin_dst,
+            pending_admin_dst,
+            quote_mints_dst,
+            referral_share_bps_dst,
+            fee_recipients_dst,
+        ) = mut_array_refs![
+            dst,
+            1,
+            32,
+            8,
+            32,
+            32,
+            32 * MAX_QUOTE_MINTS,
+            8,
+            FEE_RECIPIENT_LEN * MAX_FEE_RECIPIENTS
+        ];
         let PlatformState {
             is_initialized,
             treasury_account,
-            platform_fess,
+            platform_fee_bps,
+            admin,
+            pending_admin,
+            quote_mints,
+            referral_share_bps,
+            fee_recipients,
         } = self;
         is_initialized_dst[0] = *is_initialized as u8;
         treasury_account_dst.copy_from_slice(treasury_account.as_ref());
-        *base_percentage_dst = platform_fess.to_le_bytes();
+        *platform_fee_bps_dst = platform_fee_bps.to_le_bytes();
+        admin_dst.copy_from_slice(admin.as_ref());
+        pending_admin_dst.copy_from_slice(pending_admin.as_ref());
+        for (dst, quote_mint) in quote_mints_dst.chunks_mut(32).zip(quote_mints.iter()) {
+            dst.copy_from_slice(quote_mint.as_ref());
+        }
+        *referral_share_bps_dst = referral_share_bps.to_le_bytes();
+        for (dst, recipient) in fee_recipients_dst
+            .chunks_mut(FEE_RECIPIENT_LEN)
+            .zip(fee_recipients.iter())
+        {
+            let (account_dst, weight_bps_dst) =
+                mut_array_refs![array_mut_ref![dst, 0, FEE_RECIPIENT_LEN], 32, 8];
+            account_dst.copy_from_slice(recipient.account.as_ref());
+            *weight_bps_dst = recipient.weight_bps.to_le_bytes();
+        }
     }
 }
 
@@ -58,8 +225,69 @@ pub struct TokenState {
     pub is_initialized: bool,
     pub owner_pubkey: Pubkey,
     pub token_mint: Pubkey,
-    pub total_no_of_tokens_listed: u64,
+    pub total_no_of_tokens_listed: u64, // in base units of the mint
+    pub decimals: u8,                   // decimals of the mint
+    pub enforce_royalties: bool,        // pay Metaplex creator royalties on every Exchange
+    pub nft_mode: bool,                 // single NFT mint, listed and sold whole
 }
+
+impl TokenState {
+    /// Token state PDA of `token_mint` on the platform `platform_state`
+    pub fn find_address(
+        program_id: &Pubkey,
+        platform_state: &Pubkey,
+        token_mint: &Pubkey,
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[platform_state.as_ref(), token_mint.as_ref()], program_id)
+    }
+}
+
+/// Metaplex metadata account of `token_mint`, read for creator royalties and
+/// collection checks
+pub fn find_metadata_address(token_mint: &Pubkey) -> Pubkey {
+    let metadata_program_id = metaplex_token_metadata::id();
+    Pubkey::find_program_address(
+        &[
+            metaplex_token_metadata::state::PREFIX.as_bytes(),
+            metadata_program_id.as_ref(),
+            token_mint.as_ref(),
+        ],
+        &metadata_program_id,
+    )
+    .0
+}
+
+/// Collection of an NFT as recorded in its Metaplex metadata
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Copy, Clone)]
+pub struct MetadataCollection {
+    pub verified: bool, // signed off by the collection authority
+    pub key: Pubkey,    // mint of the collection NFT
+}
+
+/// Unpacks a Metaplex metadata account along with its collection.
+///
+/// metaplex-token-metadata 0.0.1 stops at `edition_nonce`, newer versions of
+/// the metadata program write the token standard and the collection after it.
+/// Metadata written before those fields existed leaves them zeroed or cut off
+/// and has no collection.
+pub fn unpack_metadata(
+    data: &[u8],
+) -> Result<(Metadata, Option<MetadataCollection>), ProgramError> {
+    let mut data = data;
+    let metadata =
+        Metadata::deserialize(&mut data).map_err(|_| EscrowError::InvalidMetadataAccount)?;
+    if metadata.key != Key::MetadataV1 {
+        return Err(EscrowError::InvalidMetadataAccount.into());
+    }
+    if data.is_empty() {
+        return Ok((metadata, None));
+    }
+    let (_token_standard, collection) =
+        <(Option<u8>, Option<MetadataCollection>)>::deserialize(&mut data)
+            .map_err(|_| EscrowError::InvalidMetadataAccount)?;
+    Ok((metadata, collection))
+}
+
 impl Sealed for TokenState {}
 impl IsInitialized for TokenState {
     fn is_initialized(&self) -> bool {
@@ -67,37 +295,70 @@ impl IsInitialized for TokenState {
     }
 }
 impl Pack for TokenState {
-    const LEN: usize = 73;
+    const LEN: usize = 76;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, TokenState::LEN];
-        let (is_initialized, owner_pubkey, token_mint, total_no_of_tokens_listed) =
-            array_refs![src, 1, 32, 32, 8];
+        let (
+            is_initialized,
+            owner_pubkey,
+            token_mint,
+            total_no_of_tokens_listed,
+            decimals,
+            enforce_royalties,
+            nft_mode,
+        ) = array_refs![src, 1, 32, 32, 8, 1, 1, 1];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
             _ => return Err(ProgramError::InvalidAccountData),
         };
+        let enforce_royalties = match enforce_royalties {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let nft_mode = match nft_mode {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
         Ok(TokenState {
             is_initialized,
             owner_pubkey: Pubkey::new_from_array(*owner_pubkey),
             token_mint: Pubkey::new_from_array(*token_mint),
             total_no_of_tokens_listed: u64::from_le_bytes(*total_no_of_tokens_listed),
+            decimals: decimals[0],
+            enforce_royalties,
+            nft_mode,
         })
     }
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let dst = array_mut_ref![dst, 0, TokenState::LEN];
-        let (is_initialized_dst, owner_pubkey_dst, token_mint_dst, total_no_of_tokens_listed_dst) =
-            mut_array_refs![dst, 1, 32, 32, 8];
+        let (
+            is_initialized_dst,
+            owner_pubkey_dst,
+            token_mint_dst,
+            total_no_of_tokens_listed_dst,
+            decimals_dst,
+            enforce_royalties_dst,
+            nft_mode_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 8, 1, 1, 1];
         let TokenState {
             is_initialized,
             owner_pubkey,
             token_mint,
             total_no_of_tokens_listed,
+            decimals,
+            enforce_royalties,
+            nft_mode,
         } = self;
         is_initialized_dst[0] = *is_initialized as u8;
         owner_pubkey_dst.copy_from_slice(owner_pubkey.as_ref());
         token_mint_dst.copy_from_slice(token_mint.as_ref());
         *total_no_of_tokens_listed_dst = total_no_of_tokens_listed.to_le_bytes();
+        decimals_dst[0] = *decimals;
+        enforce_royalties_dst[0] = *enforce_royalties as u8;
+        nft_mode_dst[0] = *nft_mode as u8;
     }
 }
 
@@ -107,8 +368,60 @@ pub struct ListerState {
     pub seller_pubkey: Pubkey,
     pub token_mint: Pubkey,
     pub token_account_pubkey: Pubkey,
-    pub token_amount: u64,
-    pub expected_amount_per_token: u64,
+    pub token_amount: u64,              // in base units of the mint
+    pub expected_amount_per_token: u64, // quote base units (lamports for SOL) per whole token
+    pub quote_mint: Pubkey,             // default pubkey for SOL quoted listings
+    pub listing_id: u64,                // seller chosen id, part of the listing PDA seeds
+    pub expires_at: i64,                // unix timestamp, 0 when the listing never expires
+    pub auction_end_price: u64,         // dutch auction price reached at auction_end_time
+    pub auction_start_time: i64,        // dutch auction starts at expected_amount_per_token
+    pub auction_end_time: i64,          // 0 for fixed price listings
+    pub english_auction: bool,          // sold to the highest bid of its AuctionState
+    pub swap: bool,                     // token for token swap, quote_mint needs no allowlisting
+}
+
+impl ListerState {
+    /// Listing PDA, also the authority of the escrow token account
+    pub fn find_address(
+        program_id: &Pubkey,
+        seller: &Pubkey,
+        token_mint: &Pubkey,
+        token_state: &Pubkey,
+        listing_id: u64,
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                seller.as_ref(),
+                token_mint.as_ref(),
+                token_state.as_ref(),
+                &listing_id.to_le_bytes(),
+            ],
+            program_id,
+        )
+    }
+
+    pub fn is_expired(&self, unix_timestamp: i64) -> bool {
+        self.expires_at != 0 && unix_timestamp >= self.expires_at
+    }
+
+    pub fn is_dutch_auction(&self) -> bool {
+        self.auction_end_time != 0
+    }
+
+    /// Price per whole token at `unix_timestamp`, falling linearly from the
+    /// start to the end price over a dutch auction
+    pub fn current_price(&self, unix_timestamp: i64) -> Result<u64, ProgramError> {
+        if !self.is_dutch_auction() {
+            return Ok(self.expected_amount_per_token);
+        }
+        dutch_auction_price(
+            self.expected_amount_per_token,
+            self.auction_end_price,
+            self.auction_start_time,
+            self.auction_end_time,
+            unix_timestamp,
+        )
+    }
 }
 impl Sealed for ListerState {}
 impl IsInitialized for ListerState {
@@ -117,7 +430,7 @@ impl IsInitialized for ListerState {
     }
 }
 impl Pack for ListerState {
-    const LEN: usize = 113;
+    const LEN: usize = 187;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, ListerState::LEN];
         let (
@@ -127,12 +440,30 @@ impl Pack for ListerState {
             token_account_pubkey,
             token_amount,
             expected_amount_per_token,
-        ) = array_refs![src, 1, 32, 32, 32, 8, 8];
+            quote_mint,
+            listing_id,
+            expires_at,
+            auction_end_price,
+            auction_start_time,
+            auction_end_time,
+            english_auction,
+            swap,
+        ) = array_refs![src, 1, 32, 32, 32, 8, 8, 32, 8, 8, 8, 8, 8, 1, 1];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
             _ => return Err(ProgramError::InvalidAccountData),
         };
+        let english_auction = match english_auction {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let swap = match swap {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
         Ok(ListerState {
             is_initialized,
             seller_pubkey: Pubkey::new_from_array(*seller_pubkey),
@@ -140,6 +471,14 @@ impl Pack for ListerState {
             token_account_pubkey: Pubkey::new_from_array(*token_account_pubkey),
             token_amount: u64::from_le_bytes(*token_amount),
             expected_amount_per_token: u64::from_le_bytes(*expected_amount_per_token),
+            quote_mint: Pubkey::new_from_array(*quote_mint),
+            listing_id: u64::from_le_bytes(*listing_id),
+            expires_at: i64::from_le_bytes(*expires_at),
+            auction_end_price: u64::from_le_bytes(*auction_end_price),
+            auction_start_time: i64::from_le_bytes(*auction_start_time),
+            auction_end_time: i64::from_le_bytes(*auction_end_time),
+            english_auction,
+            swap,
         })
     }
     fn pack_into_slice(&self, dst: &mut [u8]) {
@@ -151,7 +490,15 @@ impl Pack for ListerState {
             token_account_pubkey_dst,
             token_amount_dst,
             expected_amount_per_token_dst,
-        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 8];
+            quote_mint_dst,
+            listing_id_dst,
+            expires_at_dst,
+            auction_end_price_dst,
+            auction_start_time_dst,
+            auction_end_time_dst,
+            english_auction_dst,
+            swap_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 8, 32, 8, 8, 8, 8, 8, 1, 1];
         let ListerState {
             is_initialized,
             seller_pubkey,
@@ -159,6 +506,14 @@ impl Pack for ListerState {
             token_account_pubkey,
             token_amount,
             expected_amount_per_token,
+            quote_mint,
+            listing_id,
+            expires_at,
+            auction_end_price,
+            auction_start_time,
+            auction_end_time,
+            english_auction,
+            swap,
         } = self;
         is_initialized_dst[0] = *is_initialized as u8;
         seller_pubkey_dst.copy_from_slice(seller_pubkey.as_ref());
@@ -166,5 +521,321 @@ impl Pack for ListerState {
         token_account_pubkey_dst.copy_from_slice(token_account_pubkey.as_ref());
         *token_amount_dst = token_amount.to_le_bytes();
         *expected_amount_per_token_dst = expected_amount_per_token.to_le_bytes();
+        quote_mint_dst.copy_from_slice(quote_mint.as_ref());
+        *listing_id_dst = listing_id.to_le_bytes();
+        *expires_at_dst = expires_at.to_le_bytes();
+        *auction_end_price_dst = auction_end_price.to_le_bytes();
+        *auction_start_time_dst = auction_start_time.to_le_bytes();
+        *auction_end_time_dst = auction_end_time.to_le_bytes();
+        english_auction_dst[0] = *english_auction as u8;
+        swap_dst[0] = *swap as u8;
+    }
+}
+
+/// Metaplex collection allowed for NFT listings on a platform, keyed by the
+/// mint of the collection NFT
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct CollectionState {
+    pub is_initialized: bool,
+    pub collection_mint: Pubkey,
+}
+
+impl CollectionState {
+    /// Collection state PDA of `collection_mint` on the platform `platform_state`
+    pub fn find_address(
+        program_id: &Pubkey,
+        platform_state: &Pubkey,
+        collection_mint: &Pubkey,
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"collection",
+                platform_state.as_ref(),
+                collection_mint.as_ref(),
+            ],
+            program_id,
+        )
+    }
+}
+impl Sealed for CollectionState {}
+impl IsInitialized for CollectionState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for CollectionState {
+    const LEN: usize = 33;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, CollectionState::LEN];
+        let (is_initialized, collection_mint) = array_refs![src, 1, 32];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(CollectionState {
+            is_initialized,
+            collection_mint: Pubkey::new_from_array(*collection_mint),
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, CollectionState::LEN];
+        let (is_initialized_dst, collection_mint_dst) = mut_array_refs![dst, 1, 32];
+        let CollectionState {
+            is_initialized,
+            collection_mint,
+        } = self;
+        is_initialized_dst[0] = *is_initialized as u8;
+        collection_mint_dst.copy_from_slice(collection_mint.as_ref());
+    }
+}
+
+/// Buy-side offer, the offer PDA escrows the lamports to pay for the remaining
+/// `token_amount` on top of its rent
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct OfferState {
+    pub is_initialized: bool,
+    pub buyer_pubkey: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_amount: u64,    // still wanted, in base units of the mint
+    pub price_per_token: u64, // lamports per whole token
+}
+
+impl OfferState {
+    /// Offer PDA of `buyer` for `token_mint`
+    pub fn find_address(
+        program_id: &Pubkey,
+        buyer: &Pubkey,
+        token_mint: &Pubkey,
+        token_state: &Pubkey,
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"offer",
+                buyer.as_ref(),
+                token_mint.as_ref(),
+                token_state.as_ref(),
+            ],
+            program_id,
+        )
+    }
+}
+impl Sealed for OfferState {}
+impl IsInitialized for OfferState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for OfferState {
+    const LEN: usize = 81;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, OfferState::LEN];
+        let (is_initialized, buyer_pubkey, token_mint, token_amount, price_per_token) =
+            array_refs![src, 1, 32, 32, 8, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(OfferState {
+            is_initialized,
+            buyer_pubkey: Pubkey::new_from_array(*buyer_pubkey),
+            token_mint: Pubkey::new_from_array(*token_mint),
+            token_amount: u64::from_le_bytes(*token_amount),
+            price_per_token: u64::from_le_bytes(*price_per_token),
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, OfferState::LEN];
+        let (
+            is_initialized_dst,
+            buyer_pubkey_dst,
+            token_mint_dst,
+            token_amount_dst,
+            price_per_token_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 8, 8];
+        let OfferState {
+            is_initialized,
+            buyer_pubkey,
+            token_mint,
+            token_amount,
+            price_per_token,
+        } = self;
+        is_initialized_dst[0] = *is_initialized as u8;
+        buyer_pubkey_dst.copy_from_slice(buyer_pubkey.as_ref());
+        token_mint_dst.copy_from_slice(token_mint.as_ref());
+        *token_amount_dst = token_amount.to_le_bytes();
+        *price_per_token_dst = price_per_token.to_le_bytes();
+    }
+}
+
+/// English auction of a listing, the auction PDA escrows the highest bid on
+/// top of its rent until the auction is settled
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct AuctionState {
+    pub is_initialized: bool,
+    pub listing: Pubkey,
+    pub reserve_price: u64,     // lamports, lowest first bid for the whole listing
+    pub min_increment: u64,     // lamports a bid has to beat the highest bid by
+    pub end_time: i64,          // unix timestamp, pushed back by late bids
+    pub extension_secs: i64,    // bids closer than this to the end extend it, 0 for never
+    pub highest_bidder: Pubkey, // default pubkey until the first bid, gets the tokens
+    pub highest_bid: u64,       // lamports
+}
+
+impl AuctionState {
+    /// Auction PDA of `listing`
+    pub fn find_address(program_id: &Pubkey, listing: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"auction", listing.as_ref()], program_id)
+    }
+
+    pub fn has_bid(&self) -> bool {
+        self.highest_bidder != Pubkey::default()
+    }
+
+    /// Lowest bid accepted next
+    pub fn min_bid(&self) -> Option<u64> {
+        if self.has_bid() {
+            self.highest_bid.checked_add(self.min_increment)
+        } else {
+            Some(self.reserve_price)
+        }
+    }
+}
+impl Sealed for AuctionState {}
+impl IsInitialized for AuctionState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for AuctionState {
+    const LEN: usize = 105;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, AuctionState::LEN];
+        let (
+            is_initialized,
+            listing,
+            reserve_price,
+            min_increment,
+            end_time,
+            extension_secs,
+            highest_bidder,
+            highest_bid,
+        ) = array_refs![src, 1, 32, 8, 8, 8, 8, 32, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(AuctionState {
+            is_initialized,
+            listing: Pubkey::new_from_array(*listing),
+            reserve_price: u64::from_le_bytes(*reserve_price),
+            min_increment: u64::from_le_bytes(*min_increment),
+            end_time: i64::from_le_bytes(*end_time),
+            extension_secs: i64::from_le_bytes(*extension_secs),
+            highest_bidder: Pubkey::new_from_array(*highest_bidder),
+            highest_bid: u64::from_le_bytes(*highest_bid),
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, AuctionState::LEN];
+        let (
+            is_initialized_dst,
+            listing_dst,
+            reserve_price_dst,
+            min_increment_dst,
+            end_time_dst,
+            extension_secs_dst,
+            highest_bidder_dst,
+            highest_bid_dst,
+        ) = mut_array_refs![dst, 1, 32, 8, 8, 8, 8, 32, 8];
+        let AuctionState {
+            is_initialized,
+            listing,
+            reserve_price,
+            min_increment,
+            end_time,
+            extension_secs,
+            highest_bidder,
+            highest_bid,
+        } = self;
+        is_initialized_dst[0] = *is_initialized as u8;
+        listing_dst.copy_from_slice(listing.as_ref());
+        *reserve_price_dst = reserve_price.to_le_bytes();
+        *min_increment_dst = min_increment.to_le_bytes();
+        *end_time_dst = end_time.to_le_bytes();
+        *extension_secs_dst = extension_secs.to_le_bytes();
+        highest_bidder_dst.copy_from_slice(highest_bidder.as_ref());
+        *highest_bid_dst = highest_bid.to_le_bytes();
+    }
+}
+
+/// Cumulative referral earnings of a referrer on a platform, one per quote
+/// mint the referred trades were paid in
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct ReferrerState {
+    pub is_initialized: bool,
+    pub referrer: Pubkey,
+    pub quote_mint: Pubkey, // default pubkey for SOL
+    pub total_earned: u64,  // quote base units (lamports for SOL)
+}
+
+impl ReferrerState {
+    /// Referrer state PDA of `referrer` for trades quoted in `quote_mint`
+    pub fn find_address(
+        program_id: &Pubkey,
+        platform_state: &Pubkey,
+        referrer: &Pubkey,
+        quote_mint: &Pubkey,
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"referrer",
+                platform_state.as_ref(),
+                referrer.as_ref(),
+                quote_mint.as_ref(),
+            ],
+            program_id,
+        )
+    }
+}
+impl Sealed for ReferrerState {}
+impl IsInitialized for ReferrerState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for ReferrerState {
+    const LEN: usize = 73;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, ReferrerState::LEN];
+        let (is_initialized, referrer, quote_mint, total_earned) = array_refs![src, 1, 32, 32, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(ReferrerState {
+            is_initialized,
+            referrer: Pubkey::new_from_array(*referrer),
+            quote_mint: Pubkey::new_from_array(*quote_mint),
+            total_earned: u64::from_le_bytes(*total_earned),
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, ReferrerState::LEN];
+        let (is_initialized_dst, referrer_dst, quote_mint_dst, total_earned_dst) =
+            mut_array_refs![dst, 1, 32, 32, 8];
+        let ReferrerState {
+            is_initialized,
+            referrer,
+            quote_mint,
+            total_earned,
+        } = self;
+        is_initialized_dst[0] = *is_initialized as u8;
+        referrer_dst.copy_from_slice(referrer.as_ref());
+        quote_mint_dst.copy_from_slice(quote_mint.as_ref());
+        *total_earned_dst = total_earned.to_le_bytes();
     }
 }
@@ -0,0 +1,122 @@
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_pack::Pack,
+    pubkey::Pubkey, system_program, sysvar,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+use crate::error::EscrowError;
+
+//* Account checks shared by the processors, every check fails with the
+//* error passed in or with a dedicated EscrowError so clients can tell
+//* which account was rejected
+
+pub fn assert_signer(account: &AccountInfo, error: EscrowError) -> ProgramResult {
+    if !account.is_signer {
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+pub fn assert_writable(account: &AccountInfo) -> ProgramResult {
+    if !account.is_writable {
+        msg!("account not writable : {}", account.key);
+        return Err(EscrowError::AccountNotWritable.into());
+    }
+    Ok(())
+}
+
+pub fn assert_owned_by(account: &AccountInfo, owner: &Pubkey, error: EscrowError) -> ProgramResult {
+    if account.owner != owner {
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+pub fn assert_token_program(account: &AccountInfo) -> ProgramResult {
+    if *account.key != spl_token::id() {
+        return Err(EscrowError::InvalidTokenProgram.into());
+    }
+    Ok(())
+}
+
+pub fn assert_system_program(account: &AccountInfo) -> ProgramResult {
+    if *account.key != system_program::id() {
+        return Err(EscrowError::InvalidSystemProgram.into());
+    }
+    Ok(())
+}
+
+pub fn assert_associated_token_program(account: &AccountInfo) -> ProgramResult {
+    if *account.key != spl_associated_token_account::id() {
+        return Err(EscrowError::InvalidAssociatedTokenProgram.into());
+    }
+    Ok(())
+}
+
+pub fn assert_rent_sysvar(account: &AccountInfo) -> ProgramResult {
+    if *account.key != sysvar::rent::id() {
+        return Err(EscrowError::InvalidRentSysvar.into());
+    }
+    Ok(())
+}
+
+/// Checks that `referrer` is a third party to a trade, its taker and seller
+/// can't refer themselves to get part of the platform fee back
+pub fn assert_referrer(referrer: &AccountInfo, taker: &Pubkey, seller: &Pubkey) -> ProgramResult {
+    if referrer.key == taker || referrer.key == seller {
+        return Err(EscrowError::InvalidReferrer.into());
+    }
+    Ok(())
+}
+
+/// Unpacks an SPL token account, failing with `error` if it is not one
+pub fn unpack_token_account(
+    token_account: &AccountInfo,
+    error: EscrowError,
+) -> Result<TokenAccount, EscrowError> {
+    if *token_account.owner != spl_token::id() {
+        return Err(error);
+    }
+    let data = token_account.try_borrow_data().map_err(|_| error.clone())?;
+    TokenAccount::unpack(&data).map_err(|_| error)
+}
+
+/// Checks that a token account holds `mint`
+pub fn assert_token_account_mint(
+    token_account: &AccountInfo,
+    mint: &Pubkey,
+    error: EscrowError,
+) -> ProgramResult {
+    let token_account_info = unpack_token_account(token_account, error.clone())?;
+    if token_account_info.mint != *mint {
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// Checks that a token account holds `mint` and belongs to `owner`
+pub fn assert_token_account(
+    token_account: &AccountInfo,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    error: EscrowError,
+) -> ProgramResult {
+    let token_account_info = unpack_token_account(token_account, error.clone())?;
+    if token_account_info.owner != *owner || token_account_info.mint != *mint {
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// Checks that `mint` is an NFT mint: supply 1 and 0 decimals
+pub fn assert_nft_mint(mint: &AccountInfo) -> ProgramResult {
+    if *mint.owner != spl_token::id() {
+        return Err(EscrowError::InvalidMint.into());
+    }
+    let mint_info =
+        Mint::unpack(&mint.try_borrow_data()?).map_err(|_| EscrowError::InvalidMint)?;
+    if mint_info.supply != 1 || mint_info.decimals != 0 {
+        return Err(EscrowError::NotAnNft.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use solana_program::program_error::ProgramError;
+
+use smart_contarct::{error::EscrowError, fees::*};
+
+fn err<T>(error: EscrowError) -> Result<T, ProgramError> {
+    Err(error.into())
+}
+
+#[test]
+fn fee_amount_rounds_up() {
+    assert_eq!(fee_amount(1, 1).unwrap(), 1);
+    assert_eq!(fee_amount(10_000, 1).unwrap(), 1);
+    assert_eq!(fee_amount(10_001, 1).unwrap(), 2);
+    assert_eq!(fee_amount(1, 9_999).unwrap(), 1);
+    assert_eq!(fee_amount(10_000, 9_999).unwrap(), 9_999);
+    assert_eq!(fee_amount(10_001, 9_999).unwrap(), 10_000);
+    assert_eq!(fee_amount(0, 9_999).unwrap(), 0);
+    assert_eq!(fee_amount(1_000, 0).unwrap(), 0);
+}
+
+#[test]
+fn split_fee_adds_up() {
+    for total in [0, 1, 999, 10_001, 123_456_789, u64::MAX] {
+        for fee_bps in [0, 1, 250, MAX_PLATFORM_FEE_BPS, 9_999] {
+            let (fee, seller) = split_fee(total, fee_bps).unwrap();
+            assert_eq!(fee + seller, total);
+            assert_eq!(fee, fee_amount(total, fee_bps).unwrap());
+        }
+    }
+    assert_eq!(split_fee(101, 250).unwrap(), (3, 98));
+}
+
+#[test]
+fn total_price_rounds_up() {
+    assert_eq!(total_price(3, 5, 0).unwrap(), 15);
+    //* 1.5 tokens of 6 decimals at 3 lamports each
+    assert_eq!(total_price(1_500_000, 3, 6).unwrap(), 5);
+    assert_eq!(total_price(1, 1, 9).unwrap(), 1);
+    assert_eq!(total_price(0, 1_000, 9).unwrap(), 0);
+}
+
+#[test]
+fn fee_math_overflow() {
+    assert_eq!(total_price(u64::MAX, 2, 0), err(EscrowError::MathOverflow));
+    assert_eq!(
+        total_price(u64::MAX, u64::MAX, 0),
+        err(EscrowError::MathOverflow)
+    );
+    assert_eq!(total_price(1, 1, 39), err(EscrowError::MathOverflow));
+    assert_eq!(total_price(u64::MAX, 1_000_000_000, 9).unwrap(), u64::MAX);
+    assert_eq!(
+        fee_amount(u64::MAX, 2 * BPS_DENOMINATOR),
+        err(EscrowError::MathOverflow)
+    );
+    assert_eq!(
+        split_fee(u64::MAX, u64::MAX),
+        err(EscrowError::MathOverflow)
+    );
+    assert_eq!(fee_amount(u64::MAX, BPS_DENOMINATOR).unwrap(), u64::MAX);
+}
+
+#[test]
+fn platform_fee_cap() {
+    assert!(assert_valid_platform_fee(0).is_ok());
+    assert!(assert_valid_platform_fee(MAX_PLATFORM_FEE_BPS).is_ok());
+    assert_eq!(
+        assert_valid_platform_fee(MAX_PLATFORM_FEE_BPS + 1),
+        err(EscrowError::FeeTooHigh)
+    );
+}
+
+#[test]
+fn dutch_auction_price_schedule() {
+    let price = |now| dutch_auction_price(5_000, 1_000, 100, 200, now).unwrap();
+    assert_eq!(price(0), 5_000);
+    assert_eq!(price(100), 5_000);
+    assert_eq!(price(150), 3_000);
+    assert_eq!(price(200), 1_000);
+    assert_eq!(price(1_000), 1_000);
+}
+
+#[test]
+fn dutch_auction_price_rounds_up() {
+    //* 1/3 of the way through a drop of 10
+    assert_eq!(dutch_auction_price(10, 0, 0, 3, 1).unwrap(), 7);
+    assert_eq!(
+        dutch_auction_price(u64::MAX, 0, 0, i64::MAX, 1).unwrap(),
+        u64::MAX - 2
+    );
+}
+
+#[test]
+fn affordable_amount_fits_budget() {
+    assert_eq!(affordable_amount(1_000, 300, 0).unwrap(), 3);
+    assert_eq!(affordable_amount(1_000, 0, 0).unwrap(), u64::MAX);
+    assert_eq!(affordable_amount(u64::MAX, 1, 9).unwrap(), u64::MAX);
+
+    //* 6 decimals, 3 lamports per whole token
+    let amount = affordable_amount(10, 3, 6).unwrap();
+    assert_eq!(amount, 3_333_333);
+    assert!(total_price(amount, 3, 6).unwrap() <= 10);
+    assert!(total_price(amount + 1, 3, 6).unwrap() > 10);
+}
+
+#[test]
+fn referral_amount_rounds_down() {
+    assert_eq!(referral_amount(1_000, 2_500).unwrap(), 250);
+    assert_eq!(referral_amount(3, 5_000).unwrap(), 1);
+    assert_eq!(referral_amount(1_000, 0).unwrap(), 0);
+    assert_eq!(
+        referral_amount(u64::MAX, BPS_DENOMINATOR).unwrap(),
+        u64::MAX
+    );
+
+    assert!(assert_valid_referral_share(BPS_DENOMINATOR).is_ok());
+    assert!(assert_valid_referral_share(BPS_DENOMINATOR + 1).is_err());
+}
+
+#[test]
+fn capped_royalties_fit_the_cap() {
+    //* 900 of royalties scaled down to the 600 left to the seller
+    let royalties = [450, 300, 150];
+    let capped: Vec<u64> = royalties
+        .iter()
+        .map(|royalty| capped_royalty(*royalty, 900, 600).unwrap())
+        .collect();
+    assert_eq!(capped, vec![300, 200, 100]);
+
+    //* rounded down, the dust stays with the seller
+    assert_eq!(capped_royalty(1, 3, 1).unwrap(), 0);
+    let capped: u64 = royalties
+        .iter()
+        .map(|royalty| capped_royalty(*royalty, 900, 7).unwrap())
+        .sum();
+    assert!(capped <= 7);
+    assert_eq!(capped_royalty(1, 0, 1), err(EscrowError::MathOverflow));
+}
@@ -0,0 +1,375 @@
+use solana_program::{instruction::AccountMeta, pubkey::Pubkey, system_program, sysvar};
+use spl_associated_token_account::get_associated_token_address;
+
+use smart_contarct::{
+    instruction::{
+        exchange, place_order, AmountUnit, BatchFill, DutchAuction, EnglishAuction,
+        EscrowInstruction, FillMode, PlatformConfigUpdate, QuoteAccounts, INSTRUCTION_VERSION,
+    },
+    order_book::{OrderBook, Side},
+    state::{
+        FeeRecipient, ListerState, ReferrerState, TokenState, MAX_FEE_RECIPIENTS, MAX_QUOTE_MINTS,
+    },
+};
+
+#[test]
+fn pack_unpack_round_trip() {
+    let instructions = vec![
+        EscrowInstruction::CreatePlatformState { fee_bps: 250 },
+        EscrowInstruction::CreateTokenstate,
+        EscrowInstruction::ListToken {
+            args: (10, 2_000_000),
+            unit: AmountUnit::BaseUnits,
+            quote_mint: Pubkey::new_unique(),
+            listing_id: 7,
+            expires_at: 1_700_000_000,
+        },
+        EscrowInstruction::Exchange {
+            amount: 3,
+            unit: AmountUnit::WholeTokens,
+            listing_id: 7,
+            max_price_per_token: 2_000_000,
+            max_total_price: u64::MAX,
+            fill_mode: FillMode::UpTo,
+        },
+        EscrowInstruction::Cancel { listing_id: 7 },
+        EscrowInstruction::ProposeAdmin,
+        EscrowInstruction::AcceptAdmin,
+        EscrowInstruction::UpdatePlatformConfig {
+            config: PlatformConfigUpdate {
+                platform_fee_bps: Some(100),
+                treasury_account: None,
+                quote_mints: Some([Pubkey::new_unique(); MAX_QUOTE_MINTS]),
+                referral_share_bps: Some(2_500),
+                fee_recipients: Some(
+                    [FeeRecipient {
+                        account: Pubkey::new_unique(),
+                        weight_bps: 5_000,
+                    }; MAX_FEE_RECIPIENTS],
+                ),
+            },
+        },
+        EscrowInstruction::UpdateListing {
+            price: Some(5),
+            deposit: None,
+            withdraw: Some(1),
+            unit: AmountUnit::BaseUnits,
+            listing_id: 7,
+        },
+        EscrowInstruction::CloseExpiredListing { listing_id: 7 },
+        EscrowInstruction::SetRoyaltyEnforcement {
+            enforce_royalties: true,
+        },
+        EscrowInstruction::AddCollection {
+            collection_mint: Pubkey::new_unique(),
+        },
+        EscrowInstruction::RemoveCollection,
+        EscrowInstruction::CreateNftTokenState,
+        EscrowInstruction::MakeOffer {
+            token_amount: 4,
+            price_per_token: 1_000,
+            unit: AmountUnit::WholeTokens,
+        },
+        EscrowInstruction::CancelOffer,
+        EscrowInstruction::AcceptOffer {
+            amount: 2,
+            unit: AmountUnit::BaseUnits,
+        },
+        EscrowInstruction::CreateOrderBook,
+        EscrowInstruction::PlaceOrder {
+            side: Side::Ask,
+            price_per_token: 1_500,
+            amount: 8,
+            unit: AmountUnit::WholeTokens,
+        },
+        EscrowInstruction::CancelOrder {
+            side: Side::Bid,
+            order_id: 12,
+        },
+        EscrowInstruction::ListDutchAuction {
+            token_amount: 100,
+            auction: DutchAuction {
+                start_price: 5_000,
+                end_price: 1_000,
+                start_time: 1_700_000_000,
+                end_time: 1_700_086_400,
+            },
+            unit: AmountUnit::WholeTokens,
+            quote_mint: Pubkey::default(),
+            listing_id: 2,
+            expires_at: 0,
+        },
+        EscrowInstruction::ListEnglishAuction {
+            token_amount: 1,
+            auction: EnglishAuction {
+                reserve_price: 1_000_000,
+                min_increment: 100_000,
+                end_time: 1_700_086_400,
+                extension_secs: 300,
+            },
+            unit: AmountUnit::BaseUnits,
+            listing_id: 3,
+        },
+        EscrowInstruction::PlaceBid { bid: 1_200_000 },
+        EscrowInstruction::SettleAuction,
+        EscrowInstruction::BatchExchange {
+            fills: vec![
+                BatchFill {
+                    listing_id: 1,
+                    amount: 10,
+                },
+                BatchFill {
+                    listing_id: 4,
+                    amount: 25,
+                },
+            ],
+            unit: AmountUnit::WholeTokens,
+            max_amount: 30,
+            max_price_per_token: 2_000,
+            max_total_price: 50_000,
+            skip_unfillable: true,
+        },
+        EscrowInstruction::ListSwap {
+            token_amount: 500,
+            rate: 2_500_000,
+            unit: AmountUnit::WholeTokens,
+            listing_id: 5,
+            expires_at: 1_700_000_000,
+        },
+    ];
+
+    for instruction in instructions {
+        let packed = instruction.pack();
+        assert_eq!(packed[0], INSTRUCTION_VERSION);
+        assert_eq!(EscrowInstruction::unpack(&packed).unwrap(), instruction);
+    }
+}
+
+#[test]
+fn unpack_legacy_format() {
+    let mut data = vec![2];
+    data.extend_from_slice(&10u64.to_le_bytes());
+    data.extend_from_slice(&2_000_000u64.to_le_bytes());
+    assert_eq!(
+        EscrowInstruction::unpack(&data).unwrap(),
+        EscrowInstruction::ListToken {
+            args: (10, 2_000_000),
+            unit: AmountUnit::WholeTokens,
+            quote_mint: Pubkey::default(),
+            listing_id: 0,
+            expires_at: 0,
+        }
+    );
+
+    let mut data = vec![3];
+    data.extend_from_slice(&4u64.to_le_bytes());
+    assert_eq!(
+        EscrowInstruction::unpack(&data).unwrap(),
+        EscrowInstruction::Exchange {
+            amount: 4,
+            unit: AmountUnit::WholeTokens,
+            listing_id: 0,
+            max_price_per_token: u64::MAX,
+            max_total_price: u64::MAX,
+            fill_mode: FillMode::Exact,
+        }
+    );
+}
+
+#[test]
+fn unpack_legacy_platform_config() {
+    //* fee only, the fields appended later decode as None
+    let mut data = vec![7, 1];
+    data.extend_from_slice(&250u64.to_le_bytes());
+    let config = PlatformConfigUpdate {
+        platform_fee_bps: Some(250),
+        ..PlatformConfigUpdate::default()
+    };
+    assert_eq!(
+        EscrowInstruction::unpack(&data).unwrap(),
+        EscrowInstruction::UpdatePlatformConfig {
+            config: config.clone()
+        }
+    );
+
+    data.extend_from_slice(&[0, 0, 1]);
+    data.extend_from_slice(&5_000u64.to_le_bytes());
+    assert_eq!(
+        EscrowInstruction::unpack(&data).unwrap(),
+        EscrowInstruction::UpdatePlatformConfig {
+            config: PlatformConfigUpdate {
+                referral_share_bps: Some(5_000),
+                ..config
+            }
+        }
+    );
+}
+
+#[test]
+fn unpack_rejects_invalid_data() {
+    assert!(EscrowInstruction::unpack(&[]).is_err());
+    assert!(EscrowInstruction::unpack(&[42]).is_err());
+    assert!(EscrowInstruction::unpack(&[INSTRUCTION_VERSION]).is_err());
+
+    //* trailing bytes after a Borsh encoded instruction are rejected
+    let mut packed = EscrowInstruction::Cancel { listing_id: 1 }.pack();
+    packed.push(0);
+    assert!(EscrowInstruction::unpack(&packed).is_err());
+}
+
+#[test]
+fn exchange_builder_accounts() {
+    let program_id = Pubkey::new_unique();
+    let taker = Pubkey::new_unique();
+    let taker_token_account = Pubkey::new_unique();
+    let platform_state = Pubkey::new_unique();
+    let treasury = Pubkey::new_unique();
+    let seller = Pubkey::new_unique();
+    let seller_token_account = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let referrer = Pubkey::new_unique();
+    let partner_token_account = Pubkey::new_unique();
+    let quote_accounts = QuoteAccounts {
+        quote_mint: Pubkey::new_unique(),
+        taker_token_account: Pubkey::new_unique(),
+        seller_token_account: Pubkey::new_unique(),
+        treasury_token_account: Pubkey::new_unique(),
+    };
+
+    let (token_state, _) = TokenState::find_address(&program_id, &platform_state, &mint);
+    let (listing, _) = ListerState::find_address(&program_id, &seller, &mint, &token_state, 3);
+    let (referrer_state, _) = ReferrerState::find_address(
+        &program_id,
+        &platform_state,
+        &referrer,
+        &quote_accounts.quote_mint,
+    );
+
+    let instruction = exchange(
+        &program_id,
+        &taker,
+        &taker_token_account,
+        &platform_state,
+        &treasury,
+        &seller,
+        &seller_token_account,
+        &mint,
+        3,
+        5,
+        AmountUnit::BaseUnits,
+        100,
+        500,
+        FillMode::Exact,
+        Some(&quote_accounts),
+        &[partner_token_account],
+        None,
+        Some(&referrer),
+    );
+
+    assert_eq!(instruction.program_id, program_id);
+    assert_eq!(
+        instruction.accounts,
+        vec![
+            AccountMeta::new(taker, true),
+            AccountMeta::new_readonly(platform_state, false),
+            AccountMeta::new(listing, false),
+            AccountMeta::new(get_associated_token_address(&listing, &mint), false),
+            AccountMeta::new(taker_token_account, false),
+            AccountMeta::new(seller, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new(token_state, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(treasury, false),
+            AccountMeta::new(seller_token_account, false),
+            AccountMeta::new(quote_accounts.taker_token_account, false),
+            AccountMeta::new(quote_accounts.seller_token_account, false),
+            AccountMeta::new(quote_accounts.treasury_token_account, false),
+            AccountMeta::new_readonly(quote_accounts.quote_mint, false),
+            AccountMeta::new(partner_token_account, false),
+            AccountMeta::new(referrer, false),
+            AccountMeta::new(referrer_state, false),
+            AccountMeta::new(
+                get_associated_token_address(&referrer, &quote_accounts.quote_mint),
+                false,
+            ),
+        ]
+    );
+    assert_eq!(
+        EscrowInstruction::unpack(&instruction.data).unwrap(),
+        EscrowInstruction::Exchange {
+            amount: 5,
+            unit: AmountUnit::BaseUnits,
+            listing_id: 3,
+            max_price_per_token: 100,
+            max_total_price: 500,
+            fill_mode: FillMode::Exact,
+        }
+    );
+}
+
+#[test]
+fn place_order_builder_accounts() {
+    let program_id = Pubkey::new_unique();
+    let trader = Pubkey::new_unique();
+    let trader_token_account = Pubkey::new_unique();
+    let platform_state = Pubkey::new_unique();
+    let treasury = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let partner = Pubkey::new_unique();
+    let bidder = Pubkey::new_unique();
+    let evicted = Pubkey::new_unique();
+
+    let (token_state, _) = TokenState::find_address(&program_id, &platform_state, &mint);
+    let (order_book, _) = OrderBook::find_address(&program_id, &token_state);
+
+    let instruction = place_order(
+        &program_id,
+        &trader,
+        &trader_token_account,
+        &platform_state,
+        &treasury,
+        &mint,
+        Side::Ask,
+        1_000,
+        5,
+        AmountUnit::BaseUnits,
+        &[partner],
+        &[bidder],
+        Some(&evicted),
+    );
+
+    //* fee recipients, then the crossed bids and the evicted ask, each owner
+    //* followed by its associated token account as they receive tokens
+    assert_eq!(
+        instruction.accounts,
+        vec![
+            AccountMeta::new(trader, true),
+            AccountMeta::new(trader_token_account, false),
+            AccountMeta::new(order_book, false),
+            AccountMeta::new(get_associated_token_address(&order_book, &mint), false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(token_state, false),
+            AccountMeta::new_readonly(platform_state, false),
+            AccountMeta::new(treasury, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new(partner, false),
+            AccountMeta::new(bidder, false),
+            AccountMeta::new(get_associated_token_address(&bidder, &mint), false),
+            AccountMeta::new(evicted, false),
+            AccountMeta::new(get_associated_token_address(&evicted, &mint), false),
+        ]
+    );
+    assert_eq!(
+        EscrowInstruction::unpack(&instruction.data).unwrap(),
+        EscrowInstruction::PlaceOrder {
+            side: Side::Ask,
+            price_per_token: 1_000,
+            amount: 5,
+            unit: AmountUnit::BaseUnits,
+        }
+    );
+}
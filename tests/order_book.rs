@@ -0,0 +1,153 @@
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+
+use smart_contarct::{
+    error::EscrowError,
+    order_book::{OrderBook, Side, MAX_ORDERS_PER_SIDE},
+};
+
+fn empty_book() -> OrderBook {
+    OrderBook {
+        is_initialized: true,
+        token_state: Pubkey::new_unique(),
+        next_order_id: 0,
+        bids: vec![],
+        asks: vec![],
+    }
+}
+
+fn prices(book: &OrderBook, side: Side) -> Vec<(u64, u64)> {
+    book.orders(side)
+        .iter()
+        .map(|order| (order.price_per_token, order.order_id))
+        .collect()
+}
+
+#[test]
+fn orders_sorted_by_price_then_time() {
+    let mut book = empty_book();
+    let owner = Pubkey::new_unique();
+    let token_account = Pubkey::new_unique();
+
+    for price in [100, 120, 100, 90] {
+        book.insert(Side::Bid, owner, token_account, price, 1)
+            .unwrap();
+    }
+    for price in [200, 180, 200, 210] {
+        book.insert(Side::Ask, owner, token_account, price, 1)
+            .unwrap();
+    }
+
+    assert_eq!(
+        prices(&book, Side::Bid),
+        vec![(120, 1), (100, 0), (100, 2), (90, 3)]
+    );
+    assert_eq!(
+        prices(&book, Side::Ask),
+        vec![(180, 5), (200, 4), (200, 6), (210, 7)]
+    );
+    assert_eq!(book.next_order_id, 8);
+}
+
+#[test]
+fn crossing_and_fills() {
+    let mut book = empty_book();
+    let owner = Pubkey::new_unique();
+    book.insert(Side::Ask, owner, owner, 150_000, 10).unwrap();
+
+    let best = *book.best(Side::Ask).unwrap();
+    assert!(OrderBook::crosses(Side::Bid, 150_000, &best));
+    assert!(!OrderBook::crosses(Side::Bid, 149_999, &best));
+    assert!(OrderBook::crosses(Side::Ask, 150_000, &best));
+
+    assert_eq!(book.fill_best(Side::Ask, 4, 0), Ok(None));
+    assert_eq!(book.best(Side::Ask).unwrap().quantity, 6);
+    assert_eq!(book.fill_best(Side::Ask, 6, 0), Ok(None));
+    assert!(book.best(Side::Ask).is_none());
+    assert_eq!(
+        book.fill_best(Side::Ask, 1, 0),
+        Err(EscrowError::OrderNotFound.into())
+    );
+}
+
+#[test]
+fn partial_fill_drops_dust_order() {
+    let mut book = empty_book();
+    let owner = Pubkey::new_unique();
+    book.insert(Side::Bid, owner, owner, 20_000, 10).unwrap();
+
+    //* 6 left at 20,000 lamports each is still worth the minimum
+    assert_eq!(book.fill_best(Side::Bid, 4, 0), Ok(None));
+    assert_eq!(book.best(Side::Bid).unwrap().quantity, 6);
+
+    //* 4 left is worth 80,000 lamports, the order comes off the book for a refund
+    let dust = book.fill_best(Side::Bid, 2, 0).unwrap().unwrap();
+    assert_eq!((dust.owner, dust.quantity), (owner, 4));
+    assert!(book.best(Side::Bid).is_none());
+}
+
+#[test]
+fn remove_and_full_side() {
+    let mut book = empty_book();
+    let owner = Pubkey::new_unique();
+    for price in 1..=MAX_ORDERS_PER_SIDE as u64 {
+        book.insert(Side::Bid, owner, owner, price, 1).unwrap();
+    }
+    assert_eq!(
+        book.insert(Side::Bid, owner, owner, 1, 1),
+        Err::<u64, ProgramError>(EscrowError::OrderBookFull.into())
+    );
+
+    let removed = book.remove(Side::Bid, 3).unwrap();
+    assert_eq!(removed.price_per_token, 4);
+    assert_eq!(
+        book.remove(Side::Bid, 3),
+        Err(EscrowError::OrderNotFound.into())
+    );
+    assert_eq!(
+        book.remove(Side::Ask, 0),
+        Err(EscrowError::OrderNotFound.into())
+    );
+}
+
+#[test]
+fn pack_unpack_round_trip() {
+    let mut book = empty_book();
+    let owner = Pubkey::new_unique();
+    let token_account = Pubkey::new_unique();
+    book.insert(Side::Bid, owner, token_account, 100, 7)
+        .unwrap();
+    book.insert(Side::Ask, owner, token_account, 300, 9)
+        .unwrap();
+
+    let mut data = vec![0; OrderBook::LEN];
+    OrderBook::pack(book.clone(), &mut data).unwrap();
+    assert_eq!(OrderBook::unpack(&data).unwrap(), book);
+}
+
+#[test]
+fn full_side_evicts_worst_order() {
+    let mut book = empty_book();
+    let owner = Pubkey::new_unique();
+    let worst_owner = Pubkey::new_unique();
+    book.insert(Side::Ask, worst_owner, worst_owner, u64::MAX, 1)
+        .unwrap();
+    for price in 1..MAX_ORDERS_PER_SIDE as u64 {
+        book.insert(Side::Ask, owner, owner, 100 + price, 1)
+            .unwrap();
+    }
+    assert_eq!(book.evict_worst(Side::Bid, 1), Ok(None));
+
+    //* an order that does not beat the worst one is rejected
+    assert_eq!(
+        book.evict_worst(Side::Ask, u64::MAX),
+        Err(EscrowError::OrderBookFull.into())
+    );
+
+    let evicted = book.evict_worst(Side::Ask, 500).unwrap().unwrap();
+    assert_eq!(evicted.owner, worst_owner);
+    assert_eq!(evicted.price_per_token, u64::MAX);
+    assert_eq!(book.orders(Side::Ask).len(), MAX_ORDERS_PER_SIDE - 1);
+
+    let order_id = book.insert(Side::Ask, owner, owner, 500, 1).unwrap();
+    assert_eq!(book.orders(Side::Ask).last().unwrap().order_id, order_id);
+}
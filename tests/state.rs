@@ -0,0 +1,182 @@
+use borsh::BorshSerialize;
+use metaplex_token_metadata::state::{Data, Key, Metadata, MAX_METADATA_LEN};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+
+use smart_contarct::state::{
+    unpack_metadata, AuctionState, FeeRecipient, MetadataCollection, PlatformState,
+    MAX_FEE_RECIPIENTS, MAX_QUOTE_MINTS,
+};
+
+fn auction() -> AuctionState {
+    AuctionState {
+        is_initialized: true,
+        listing: Pubkey::new_unique(),
+        reserve_price: 1_000,
+        min_increment: 100,
+        end_time: 1_700_000_000,
+        extension_secs: 300,
+        highest_bidder: Pubkey::default(),
+        highest_bid: 0,
+    }
+}
+
+#[test]
+fn auction_state_round_trip() {
+    let mut auction = auction();
+    auction.highest_bidder = Pubkey::new_unique();
+    auction.highest_bid = 1_500;
+
+    let mut data = vec![0; AuctionState::LEN];
+    AuctionState::pack(auction, &mut data).unwrap();
+    assert_eq!(AuctionState::unpack(&data).unwrap(), auction);
+}
+
+#[test]
+fn auction_min_bid() {
+    let mut auction = auction();
+    assert!(!auction.has_bid());
+    assert_eq!(auction.min_bid(), Some(1_000));
+
+    auction.highest_bidder = Pubkey::new_unique();
+    auction.highest_bid = 1_500;
+    assert_eq!(auction.min_bid(), Some(1_600));
+
+    auction.highest_bid = u64::MAX;
+    assert_eq!(auction.min_bid(), None);
+}
+
+fn platform(treasury_account: Pubkey) -> PlatformState {
+    PlatformState {
+        is_initialized: true,
+        treasury_account,
+        platform_fee_bps: 250,
+        admin: Pubkey::new_unique(),
+        pending_admin: Pubkey::default(),
+        quote_mints: [Pubkey::default(); MAX_QUOTE_MINTS],
+        referral_share_bps: 1_000,
+        fee_recipients: [FeeRecipient::default(); MAX_FEE_RECIPIENTS],
+    }
+}
+
+#[test]
+fn platform_state_round_trip() {
+    let mut platform = platform(Pubkey::new_unique());
+    platform.quote_mints[1] = Pubkey::new_unique();
+    platform.fee_recipients[0] = FeeRecipient {
+        account: platform.treasury_account,
+        weight_bps: 6_000,
+    };
+    platform.fee_recipients[2] = FeeRecipient {
+        account: Pubkey::new_unique(),
+        weight_bps: 4_000,
+    };
+
+    let mut data = vec![0; PlatformState::LEN];
+    PlatformState::pack(platform, &mut data).unwrap();
+    assert_eq!(PlatformState::unpack(&data).unwrap(), platform);
+}
+
+#[test]
+fn fee_split_validation() {
+    let treasury = Pubkey::new_unique();
+    let partner = Pubkey::new_unique();
+    let mut platform = platform(treasury);
+    assert!(platform.is_valid_fee_split());
+
+    platform.fee_recipients[0] = FeeRecipient {
+        account: treasury,
+        weight_bps: 7_000,
+    };
+    platform.fee_recipients[1] = FeeRecipient {
+        account: partner,
+        weight_bps: 3_000,
+    };
+    assert!(platform.is_valid_fee_split());
+
+    //* weights have to add up to the whole fee
+    platform.fee_recipients[1].weight_bps = 2_000;
+    assert!(!platform.is_valid_fee_split());
+
+    //* the treasury has to be a recipient
+    let mut without_treasury = platform;
+    without_treasury.fee_recipients[0].account = Pubkey::new_unique();
+    without_treasury.fee_recipients[1].weight_bps = 3_000;
+    assert!(!without_treasury.is_valid_fee_split());
+
+    //* recipients are distinct
+    platform.fee_recipients[1].account = treasury;
+    platform.fee_recipients[1].weight_bps = 3_000;
+    assert!(!platform.is_valid_fee_split());
+}
+
+#[test]
+fn platform_fee_split_dust_to_treasury() {
+    let treasury = Pubkey::new_unique();
+    let mut platform = platform(treasury);
+    assert_eq!(platform.split_platform_fee(101).unwrap(), (101, vec![]));
+
+    platform.fee_recipients = [
+        FeeRecipient {
+            account: Pubkey::new_unique(),
+            weight_bps: 3_333,
+        },
+        FeeRecipient {
+            account: treasury,
+            weight_bps: 3_334,
+        },
+        FeeRecipient::default(),
+        FeeRecipient {
+            account: Pubkey::new_unique(),
+            weight_bps: 3_333,
+        },
+    ];
+    assert!(platform.is_valid_fee_split());
+    assert_eq!(
+        platform.split_platform_fee(101).unwrap(),
+        (35, vec![33, 33])
+    );
+    assert_eq!(platform.split_platform_fee(0).unwrap(), (0, vec![0, 0]));
+}
+
+fn metadata(mint: Pubkey) -> Vec<u8> {
+    Metadata {
+        key: Key::MetadataV1,
+        update_authority: Pubkey::new_unique(),
+        mint,
+        data: Data {
+            name: "Token".to_string(),
+            symbol: "TKN".to_string(),
+            uri: "https://example.com".to_string(),
+            seller_fee_basis_points: 500,
+            creators: None,
+        },
+        primary_sale_happened: true,
+        is_mutable: false,
+        edition_nonce: Some(255),
+    }
+    .try_to_vec()
+    .unwrap()
+}
+
+#[test]
+fn metadata_collection_past_v1_layout() {
+    let mint = Pubkey::new_unique();
+    let collection = MetadataCollection {
+        verified: true,
+        key: Pubkey::new_unique(),
+    };
+
+    //* token standard and collection written after the 0.0.1 fields
+    let mut data = metadata(mint);
+    data.extend((Some(0u8), Some(collection)).try_to_vec().unwrap());
+    data.resize(MAX_METADATA_LEN, 0);
+    let (unpacked, unpacked_collection) = unpack_metadata(&data).unwrap();
+    assert_eq!(unpacked.mint, mint);
+    assert_eq!(unpacked_collection, Some(collection));
+
+    //* metadata written before collections existed has a zeroed tail
+    let mut data = metadata(mint);
+    data.resize(MAX_METADATA_LEN, 0);
+    assert_eq!(unpack_metadata(&data).unwrap().1, None);
+    assert_eq!(unpack_metadata(&metadata(mint)).unwrap().1, None);
+}
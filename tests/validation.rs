@@ -0,0 +1,245 @@
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, program_option::COption,
+    program_pack::Pack, pubkey::Pubkey, system_program, sysvar,
+};
+use spl_token::state::{Account as TokenAccount, AccountState};
+
+use smart_contarct::{error::EscrowError, validation::*};
+
+struct TestAccount {
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+}
+
+impl TestAccount {
+    fn new(owner: Pubkey) -> Self {
+        Self::with_key(Pubkey::new_unique(), owner)
+    }
+
+    fn with_key(key: Pubkey, owner: Pubkey) -> Self {
+        TestAccount {
+            key,
+            owner,
+            lamports: 0,
+            data: vec![],
+        }
+    }
+
+    fn token_account(mint: Pubkey, authority: Pubkey) -> Self {
+        let mut account = Self::new(spl_token::id());
+        account.data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: authority,
+                amount: 0,
+                delegate: COption::None,
+                state: AccountState::Initialized,
+                is_native: COption::None,
+                delegated_amount: 0,
+                close_authority: COption::None,
+            },
+            &mut account.data,
+        )
+        .unwrap();
+        account
+    }
+
+    fn info(&mut self, is_signer: bool, is_writable: bool) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            is_signer,
+            is_writable,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            false,
+            0,
+        )
+    }
+}
+
+fn err(error: EscrowError) -> Result<(), ProgramError> {
+    Err(error.into())
+}
+
+#[test]
+fn signer_check() {
+    let mut account = TestAccount::new(system_program::id());
+    assert_eq!(
+        assert_signer(&account.info(false, true), EscrowError::SellerNotSigner),
+        err(EscrowError::SellerNotSigner)
+    );
+    assert_eq!(
+        assert_signer(&account.info(true, true), EscrowError::SellerNotSigner),
+        Ok(())
+    );
+}
+
+#[test]
+fn writable_check() {
+    let mut account = TestAccount::new(system_program::id());
+    assert_eq!(
+        assert_writable(&account.info(false, false)),
+        err(EscrowError::AccountNotWritable)
+    );
+    assert_eq!(assert_writable(&account.info(false, true)), Ok(()));
+}
+
+#[test]
+fn owner_check() {
+    let program_id = Pubkey::new_unique();
+    let mut foreign = TestAccount::new(Pubkey::new_unique());
+    assert_eq!(
+        assert_owned_by(
+            &foreign.info(false, true),
+            &program_id,
+            EscrowError::TokenStateNotOwnedByProgram
+        ),
+        err(EscrowError::TokenStateNotOwnedByProgram)
+    );
+
+    let mut owned = TestAccount::new(program_id);
+    assert_eq!(
+        assert_owned_by(
+            &owned.info(false, true),
+            &program_id,
+            EscrowError::TokenStateNotOwnedByProgram
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn program_id_checks() {
+    let mut fake = TestAccount::new(Pubkey::new_unique());
+    let fake = fake.info(false, false);
+    assert_eq!(
+        assert_token_program(&fake),
+        err(EscrowError::InvalidTokenProgram)
+    );
+    assert_eq!(
+        assert_system_program(&fake),
+        err(EscrowError::InvalidSystemProgram)
+    );
+    assert_eq!(
+        assert_associated_token_program(&fake),
+        err(EscrowError::InvalidAssociatedTokenProgram)
+    );
+    assert_eq!(assert_rent_sysvar(&fake), err(EscrowError::InvalidRentSysvar));
+
+    let mut token_program = TestAccount::with_key(spl_token::id(), Pubkey::new_unique());
+    assert_eq!(assert_token_program(&token_program.info(false, false)), Ok(()));
+    let mut system = TestAccount::with_key(system_program::id(), Pubkey::new_unique());
+    assert_eq!(assert_system_program(&system.info(false, false)), Ok(()));
+    let mut rent = TestAccount::with_key(sysvar::rent::id(), sysvar::id());
+    assert_eq!(assert_rent_sysvar(&rent.info(false, false)), Ok(()));
+}
+
+#[test]
+fn referrer_is_a_third_party() {
+    let taker = Pubkey::new_unique();
+    let seller = Pubkey::new_unique();
+
+    let mut self_referral = TestAccount::with_key(taker, system_program::id());
+    assert_eq!(
+        assert_referrer(&self_referral.info(false, true), &taker, &seller),
+        err(EscrowError::InvalidReferrer)
+    );
+    let mut seller_referral = TestAccount::with_key(seller, system_program::id());
+    assert_eq!(
+        assert_referrer(&seller_referral.info(false, true), &taker, &seller),
+        err(EscrowError::InvalidReferrer)
+    );
+
+    let mut referrer = TestAccount::new(system_program::id());
+    assert_eq!(
+        assert_referrer(&referrer.info(false, true), &taker, &seller),
+        Ok(())
+    );
+}
+
+#[test]
+fn token_account_not_owned_by_token_program() {
+    let mint = Pubkey::new_unique();
+    let authority = Pubkey::new_unique();
+    let mut account = TestAccount::token_account(mint, authority);
+    account.owner = Pubkey::new_unique();
+    assert_eq!(
+        assert_token_account(
+            &account.info(false, true),
+            &authority,
+            &mint,
+            EscrowError::InvalidEscrowTokenAccount
+        ),
+        err(EscrowError::InvalidEscrowTokenAccount)
+    );
+}
+
+#[test]
+fn token_account_uninitialized() {
+    let mut account = TestAccount::new(spl_token::id());
+    account.data = vec![0; TokenAccount::LEN];
+    assert_eq!(
+        assert_token_account_mint(
+            &account.info(false, true),
+            &Pubkey::new_unique(),
+            EscrowError::InvalidTakerTokenAccount
+        ),
+        err(EscrowError::InvalidTakerTokenAccount)
+    );
+}
+
+#[test]
+fn token_account_wrong_mint_or_authority() {
+    let mint = Pubkey::new_unique();
+    let authority = Pubkey::new_unique();
+    let mut account = TestAccount::token_account(mint, authority);
+
+    assert_eq!(
+        assert_token_account(
+            &account.info(false, true),
+            &authority,
+            &Pubkey::new_unique(),
+            EscrowError::InvalidSellerTokenAccount
+        ),
+        err(EscrowError::InvalidSellerTokenAccount)
+    );
+    assert_eq!(
+        assert_token_account(
+            &account.info(false, true),
+            &Pubkey::new_unique(),
+            &mint,
+            EscrowError::InvalidSellerTokenAccount
+        ),
+        err(EscrowError::InvalidSellerTokenAccount)
+    );
+    assert_eq!(
+        assert_token_account_mint(
+            &account.info(false, true),
+            &Pubkey::new_unique(),
+            EscrowError::InvalidTakerTokenAccount
+        ),
+        err(EscrowError::InvalidTakerTokenAccount)
+    );
+
+    assert_eq!(
+        assert_token_account(
+            &account.info(false, true),
+            &authority,
+            &mint,
+            EscrowError::InvalidSellerTokenAccount
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        assert_token_account_mint(
+            &account.info(false, true),
+            &mint,
+            EscrowError::InvalidTakerTokenAccount
+        ),
+        Ok(())
+    );
+}